@@ -0,0 +1,198 @@
+#[cfg(test)]
+pub use self::headless::HeadlessBackend;
+use crate::screen::CellStyle;
+use futures::Poll;
+use std::io;
+use termion::event::Event;
+
+pub enum TtyEvent {
+    Resize((u16, u16)),
+    Input(Event),
+}
+
+/// The terminal vix runs in: its input events and the operations the screen
+/// is drawn with. Positions start at 0.
+pub trait Backend {
+    /// Returns the next input or resize event, the task is woken up when
+    /// there is none yet.
+    fn poll_event(&mut self) -> Poll<Option<TtyEvent>, ()>;
+
+    fn size(&self) -> io::Result<(u16, u16)>;
+
+    fn goto(&mut self, x: u16, y: u16) -> io::Result<()>;
+
+    fn set_style(&mut self, style: CellStyle) -> io::Result<()>;
+
+    fn write(&mut self, text: &str) -> io::Result<()>;
+
+    fn clear(&mut self) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()>;
+
+    /// Gives the terminal back to the shell until vix is continued.
+    fn suspend(&mut self) -> io::Result<()>;
+}
+
+#[cfg(test)]
+mod headless {
+    use super::{Backend, TtyEvent};
+    use crate::screen::{Cell, CellStyle};
+    use crate::wrap;
+    use futures::task::{self, Task};
+    use futures::{Async, Poll};
+    use std::collections::VecDeque;
+    use std::io;
+    use std::mem;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    /// A terminal kept in memory, to run vix without a TTY in tests.
+    /// Events are queued with `push_event`, and what was drawn is read back
+    /// with `row_text`, `cell` and `cursor`.
+    pub struct HeadlessBackend {
+        width: u16,
+        height: u16,
+        cells: Vec<Cell>,
+        cursor: (u16, u16),
+        style: CellStyle,
+        events: VecDeque<TtyEvent>,
+        task: Option<Task>,
+        /// The text written since the last `take_writes`, with its position.
+        writes: Vec<((u16, u16), String)>,
+        clears: usize,
+    }
+
+    impl HeadlessBackend {
+        /// Creates a blank terminal, reporting its size as the first event.
+        pub fn new(width: u16, height: u16) -> Self {
+            let mut backend = HeadlessBackend {
+                width,
+                height,
+                cells: vec![Cell::default(); width as usize * height as usize],
+                cursor: (0, 0),
+                style: CellStyle::default(),
+                events: VecDeque::new(),
+                task: None,
+                writes: Vec::new(),
+                clears: 0,
+            };
+            backend.push_event(TtyEvent::Resize((width, height)));
+            backend
+        }
+
+        pub fn push_event(&mut self, event: TtyEvent) {
+            self.events.push_back(event);
+            if let Some(task) = self.task.take() {
+                task.notify();
+            }
+        }
+
+        /// Changes the size, blanking the terminal as resizing may.
+        pub fn resize(&mut self, width: u16, height: u16) {
+            self.width = width;
+            self.height = height;
+            self.cells = vec![Cell::default(); width as usize * height as usize];
+            self.push_event(TtyEvent::Resize((width, height)));
+        }
+
+        pub fn cell(&self, x: u16, y: u16) -> Option<&Cell> {
+            if x >= self.width || y >= self.height {
+                return None;
+            }
+            self.cells
+                .get(y as usize * self.width as usize + x as usize)
+        }
+
+        pub fn row_text(&self, y: u16) -> String {
+            (0..self.width)
+                .filter_map(|x| self.cell(x, y))
+                .map(|cell| cell.text.as_str())
+                .collect()
+        }
+
+        pub fn cursor(&self) -> (u16, u16) {
+            self.cursor
+        }
+
+        pub fn take_writes(&mut self) -> Vec<((u16, u16), String)> {
+            mem::take(&mut self.writes)
+        }
+
+        /// The number of times the whole terminal was blanked.
+        pub fn clears(&self) -> usize {
+            self.clears
+        }
+    }
+
+    impl Backend for HeadlessBackend {
+        fn poll_event(&mut self) -> Poll<Option<TtyEvent>, ()> {
+            match self.events.pop_front() {
+                Some(event) => Ok(Async::Ready(Some(event))),
+                None => {
+                    self.task = Some(task::current());
+                    Ok(Async::NotReady)
+                }
+            }
+        }
+
+        fn size(&self) -> io::Result<(u16, u16)> {
+            Ok((self.width, self.height))
+        }
+
+        fn goto(&mut self, x: u16, y: u16) -> io::Result<()> {
+            self.cursor = (x, y);
+            Ok(())
+        }
+
+        fn set_style(&mut self, style: CellStyle) -> io::Result<()> {
+            self.style = style;
+            Ok(())
+        }
+
+        fn write(&mut self, text: &str) -> io::Result<()> {
+            self.writes.push((self.cursor, text.to_owned()));
+            let (mut x, y) = self.cursor;
+            for grapheme in text.graphemes(true) {
+                let width = wrap::grapheme_width(grapheme, 0, 1);
+                if width == 0 {
+                    continue;
+                }
+                if y >= self.height || x + width > self.width {
+                    break;
+                }
+                let idx = y as usize * self.width as usize + x as usize;
+                self.cells[idx] = Cell {
+                    text: grapheme.to_owned(),
+                    style: self.style,
+                };
+                for covered in 1..width as usize {
+                    self.cells[idx + covered] = Cell {
+                        text: String::new(),
+                        style: self.style,
+                    };
+                }
+                x += width;
+            }
+            self.cursor = (x, y);
+            Ok(())
+        }
+
+        fn clear(&mut self) -> io::Result<()> {
+            self.clears += 1;
+            for cell in &mut self.cells {
+                *cell = Cell {
+                    style: self.style,
+                    ..Cell::default()
+                };
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+
+        fn suspend(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -1,8 +1,18 @@
-use futures::Future;
+use futures::{future, Future};
 use serde_json::Value;
-use tokio;
-use xrl;
-use xrl::{ClientResult, ModifySelection};
+use xrl::{ClientError, ModifySelection};
+
+pub type ClientResult<T> = Box<dyn Future<Item = T, Error = ClientError> + Send>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditStep {
+    Click(u64, u64),
+    Drag(u64, u64),
+    LineEnd,
+    Paste(String),
+    Delete,
+    Backspace,
+}
 
 pub struct Client {
     inner: xrl::Client,
@@ -22,6 +32,22 @@ impl Client {
         tokio::spawn(f);
     }
 
+    /// Replaces the character after the cursor, keeping the cursor on it.
+    pub fn replace_char(&mut self, chr: char) {
+        let inner = self.inner.clone();
+        let view_id = self.view_id;
+        let f = self
+            .inner
+            .right_sel(view_id)
+            .and_then({
+                let inner = inner.clone();
+                move |_| inner.char(view_id, chr)
+            })
+            .and_then(move |_| inner.left(view_id))
+            .map_err(|_| ());
+        tokio::spawn(f);
+    }
+
     pub fn scroll(&mut self, start: u64, end: u64) {
         let f = self.inner.scroll(self.view_id, start, end).map_err(|_| ());
         tokio::spawn(f);
@@ -38,23 +64,29 @@ impl Client {
     }
 
     pub fn select_line(&mut self) {
-        // FIXME: Find non blocking way by chaining
-        self.inner.line_start(self.view_id).wait().unwrap();
-        self.inner.line_end_sel(self.view_id).wait().unwrap();
-        // let end_sel = self.inner.line_end_sel(self.view_id).map_err(|_| ());
-        // let sel = self.inner.line_start(self.view_id).and_then(|_| {
-        //     info!("end sel spawn");
-        //     tokio::spawn(end_sel);
-        //     Ok(())
-        // }).map_err(|_| ());
-        // tokio::spawn(sel);
+        let inner = self.inner.clone();
+        let view_id = self.view_id;
+        let f = self
+            .inner
+            .line_start(view_id)
+            .and_then(move |_| inner.line_end_sel(view_id))
+            .map_err(|_| ());
+        tokio::spawn(f);
     }
 
     pub fn delete_line(&mut self) {
-        // FIXME: Find non blocking way by chaining
-        self.inner.line_start(self.view_id).wait().unwrap();
-        self.inner.line_end_sel(self.view_id).wait().unwrap();
-        self.inner.delete(self.view_id).wait().unwrap();
+        let inner = self.inner.clone();
+        let view_id = self.view_id;
+        let f = self
+            .inner
+            .line_start(view_id)
+            .and_then({
+                let inner = inner.clone();
+                move |_| inner.line_end_sel(view_id)
+            })
+            .and_then(move |_| inner.delete(view_id))
+            .map_err(|_| ());
+        tokio::spawn(f);
     }
 
     pub fn goto_line(&mut self, line: u64) {
@@ -63,7 +95,7 @@ impl Client {
     }
 
     pub fn copy(&mut self) -> ClientResult<Value> {
-        self.inner.copy(self.view_id)
+        Box::new(self.inner.copy(self.view_id))
     }
 
     pub fn paste(&mut self, buffer: &str) {
@@ -72,7 +104,7 @@ impl Client {
     }
 
     pub fn cut(&mut self) -> ClientResult<Value> {
-        self.inner.cut(self.view_id)
+        Box::new(self.inner.cut(self.view_id))
     }
 
     pub fn undo(&mut self) {
@@ -131,11 +163,6 @@ impl Client {
         tokio::spawn(f);
     }
 
-    pub fn find_all(&mut self) {
-        let f = self.inner.find_all(self.view_id).map_err(|_| ());
-        tokio::spawn(f);
-    }
-
     pub fn highlight_find(&mut self, visible: bool) {
         let f = self
             .inner
@@ -195,7 +222,7 @@ impl Client {
     }
 
     pub fn save(&mut self, file: &str) -> ClientResult<()> {
-        self.inner.save(self.view_id, file)
+        Box::new(self.inner.save(self.view_id, file))
     }
 
     pub fn click(&mut self, line: u64, column: u64) {
@@ -210,4 +237,30 @@ impl Client {
         let f = self.inner.drag(self.view_id, line, column).map_err(|_| ());
         tokio::spawn(f);
     }
+
+    /// Sends `steps` one after the other. The response to the `copy`
+    /// request sent last comes after the updates for all of them.
+    pub fn edit(&mut self, steps: Vec<EditStep>) -> ClientResult<Value> {
+        let view_id = self.view_id;
+        let sent = steps.into_iter().fold(
+            Box::new(future::ok(())) as ClientResult<()>,
+            |sent, step| {
+                let inner = self.inner.clone();
+                Box::new(sent.and_then(move |_| -> ClientResult<()> {
+                    match step {
+                        EditStep::Click(line, column) => {
+                            Box::new(inner.click_point_select(view_id, line, column))
+                        }
+                        EditStep::Drag(line, column) => Box::new(inner.drag(view_id, line, column)),
+                        EditStep::LineEnd => Box::new(inner.line_end(view_id)),
+                        EditStep::Paste(text) => Box::new(inner.paste(view_id, &text)),
+                        EditStep::Delete => Box::new(inner.delete(view_id)),
+                        EditStep::Backspace => Box::new(inner.backspace(view_id)),
+                    }
+                }))
+            },
+        );
+        let inner = self.inner.clone();
+        Box::new(sent.and_then(move |_| inner.copy(view_id)))
+    }
 }
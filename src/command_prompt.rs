@@ -1,20 +1,47 @@
-use std::io::{Error, Write};
+use crate::layout::SplitDirection;
+use crate::screen::{CellStyle, Screen};
+use crate::search::SearchDirection;
+use std::fmt;
 use std::str;
 use std::str::FromStr;
-use termion::clear::CurrentLine;
-use termion::cursor::Goto;
 use termion::event::{Event, Key};
 use xrl::ViewId;
 
 #[derive(Debug)]
 pub enum Command {
-    Search(String),
+    Search(String, SearchDirection),
     Cancel,
-    Quit,
+    Quit(bool),
+    QuitAll(bool),
     Save(Option<ViewId>, bool),
+    SaveAll(bool),
+    SaveModified(bool),
     GotoLine(u64),
     Open(Option<String>),
     SetTheme(String),
+    Set(Vec<String>),
+    VimGrep {
+        pattern: String,
+        files: Vec<String>,
+        all: bool,
+        jump: bool,
+    },
+    Grep(String),
+    QuickfixOpen,
+    QuickfixNext,
+    QuickfixPrev,
+    QuickfixGoto(Option<usize>),
+    Split(SplitDirection, Option<String>),
+    CloseWindow,
+    OnlyWindow,
+    TabNew(Option<String>),
+    TabNext,
+    TabPrev,
+    TabClose,
+    TabOnly,
+    CheckTime,
+    UndoStep(i64),
+    UndoTime(i64),
 }
 
 #[derive(Debug)]
@@ -32,6 +59,18 @@ pub enum ParseCommandError {
         found: usize,
     },*/
     UnknownCommand(String),
+    ExpectedArgument(String),
+    InvalidArgument(String),
+}
+
+impl fmt::Display for ParseCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseCommandError::UnknownCommand(cmd) => write!(f, "Not an editor command: {}", cmd),
+            ParseCommandError::ExpectedArgument(cmd) => write!(f, "Argument required: {}", cmd),
+            ParseCommandError::InvalidArgument(arg) => write!(f, "Invalid argument: {}", arg),
+        }
+    }
 }
 
 impl FromStr for Command {
@@ -42,29 +81,66 @@ impl FromStr for Command {
         if let Ok(idx) = num.parse::<u64>() {
             Ok(Command::GotoLine(idx))
         } else {
-            match &s[..] {
+            match s {
                 // FIXME: Unsure how tow handle the ! operator here
                 "w" | "write" => Ok(Command::Save(None, false)),
-                "q" | "quit" => Ok(Command::Quit),
-                // FIXME: Parent future (Xim) exits before save future is complete
+                "q" | "quit" => Ok(Command::Quit(false)),
+                "q!" | "quit!" => Ok(Command::Quit(true)),
+                "qa" | "qall" | "quita" | "quitall" => Ok(Command::QuitAll(false)),
+                "qa!" | "qall!" | "quita!" | "quitall!" => Ok(Command::QuitAll(true)),
                 "wq" => Ok(Command::Save(None, true)),
+                "wa" | "wall" => Ok(Command::SaveAll(false)),
+                "wqa" | "wqall" | "xa" | "xall" => Ok(Command::SaveAll(true)),
+                "x" | "xit" | "exi" | "exit" => Ok(Command::SaveModified(true)),
+                "copen" | "cope" => Ok(Command::QuickfixOpen),
+                "cn" | "cnext" => Ok(Command::QuickfixNext),
+                "cp" | "cprevious" | "cN" | "cNext" => Ok(Command::QuickfixPrev),
+                "clo" | "close" => Ok(Command::CloseWindow),
+                "on" | "only" => Ok(Command::OnlyWindow),
+                "checkt" | "checktime" => Ok(Command::CheckTime),
+                "tabn" | "tabnext" => Ok(Command::TabNext),
+                "tabp" | "tabprevious" | "tabN" | "tabNext" => Ok(Command::TabPrev),
+                "tabc" | "tabclose" => Ok(Command::TabClose),
+                "tabo" | "tabonly" => Ok(Command::TabOnly),
                 command => {
                     let mut parts: Vec<&str> = command.split(' ').collect();
                     let cmd = parts.remove(0);
+                    let args = command[cmd.len()..].trim_start();
                     match cmd {
-                        "set" => {
-                            let sub = parts.remove(0);
-                            match sub {
-                                "theme" => {
-                                    if parts.is_empty() {
-                                        Ok(Command::SetTheme(parts[0].to_owned()))
-                                    } else {
-                                        Ok(Command::SetTheme(String::default()))
-                                    }
-                                }
-                                _ => Err(ParseCommandError::UnknownCommand(command.into())),
+                        "set" | "se" => match parts.first() {
+                            Some(&"theme") => match parts.get(1) {
+                                Some(theme) => Ok(Command::SetTheme((*theme).to_owned())),
+                                None => Ok(Command::SetTheme(String::default())),
+                            },
+                            Some(_) => Ok(Command::Set(split_args(args))),
+                            None => Err(ParseCommandError::UnknownCommand(command.into())),
+                        },
+                        "vimgrep" | "vim" => parse_vimgrep(args),
+                        "grep" => {
+                            if args.is_empty() {
+                                Err(ParseCommandError::ExpectedArgument(command.into()))
+                            } else {
+                                Ok(Command::Grep(args.to_owned()))
                             }
                         }
+                        "cc" => match args {
+                            "" => Ok(Command::QuickfixGoto(None)),
+                            nr => match nr.parse::<usize>() {
+                                Ok(nr) => Ok(Command::QuickfixGoto(Some(nr))),
+                                Err(_) => Err(ParseCommandError::InvalidArgument(command.into())),
+                            },
+                        },
+                        "sp" | "split" => Ok(Command::Split(
+                            SplitDirection::Horizontal,
+                            optional_arg(args),
+                        )),
+                        "vs" | "vsp" | "vsplit" => {
+                            Ok(Command::Split(SplitDirection::Vertical, optional_arg(args)))
+                        }
+                        "ea" | "earlier" => parse_undo_amount(args, -1, command),
+                        "lat" | "later" => parse_undo_amount(args, 1, command),
+                        "tabnew" => Ok(Command::TabNew(optional_arg(args))),
+                        "tabe" | "tabedit" => Ok(Command::TabNew(optional_arg(args))),
                         _ => Err(ParseCommandError::UnknownCommand(command.into())),
                     }
                 }
@@ -73,10 +149,135 @@ impl FromStr for Command {
     }
 }
 
+/// Parses `{N}` as undo steps or `{N}s`, `{N}m`, `{N}h` and `{N}d` as time.
+fn parse_undo_amount(args: &str, sign: i64, command: &str) -> Result<Command, ParseCommandError> {
+    if args.is_empty() {
+        return Ok(Command::UndoStep(sign));
+    }
+    let split = args
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(args.len());
+    let count = match args[..split].parse::<i64>() {
+        Ok(count) => count * sign,
+        Err(_) => return Err(ParseCommandError::InvalidArgument(command.into())),
+    };
+    match &args[split..] {
+        "" => Ok(Command::UndoStep(count)),
+        "s" => Ok(Command::UndoTime(count)),
+        "m" => Ok(Command::UndoTime(count * 60)),
+        "h" => Ok(Command::UndoTime(count * 60 * 60)),
+        "d" => Ok(Command::UndoTime(count * 24 * 60 * 60)),
+        _ => Err(ParseCommandError::InvalidArgument(command.into())),
+    }
+}
+
+fn optional_arg(args: &str) -> Option<String> {
+    if args.is_empty() {
+        None
+    } else {
+        Some(args.to_owned())
+    }
+}
+
+/// Splits command arguments at whitespace, except for spaces escaped with a
+/// backslash, e.g. `:set grepprg=rg\ --vimgrep`.
+fn split_args(args: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut part = String::new();
+    let mut chars = args.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(' ') => part.push(' '),
+                Some(c) => {
+                    part.push('\\');
+                    part.push(c);
+                }
+                None => part.push('\\'),
+            },
+            c if c.is_whitespace() => {
+                if !part.is_empty() {
+                    parts.push(part.split_off(0));
+                }
+            }
+            c => part.push(c),
+        }
+    }
+    if !part.is_empty() {
+        parts.push(part);
+    }
+    parts
+}
+
+/// Parses the arguments of `:vimgrep /{pattern}/[g][j] {file} ...` or
+/// `:vimgrep {pattern} {file} ...`.
+fn parse_vimgrep(args: &str) -> Result<Command, ParseCommandError> {
+    let delimiter = match args.chars().next() {
+        Some(c) if !c.is_alphanumeric() && c != '\\' && c != '"' && c != '|' => c,
+        Some(_) => {
+            let mut parts = args.split_whitespace();
+            let pattern = parts.next().unwrap_or_default().to_owned();
+            let files: Vec<String> = parts.map(str::to_owned).collect();
+            if files.is_empty() {
+                return Err(ParseCommandError::ExpectedArgument(args.into()));
+            }
+            return Ok(Command::VimGrep {
+                pattern,
+                files,
+                all: false,
+                jump: true,
+            });
+        }
+        None => return Err(ParseCommandError::ExpectedArgument("vimgrep".into())),
+    };
+
+    let body = &args[delimiter.len_utf8()..];
+    let mut pattern = String::new();
+    let mut end = None;
+    let mut chars = body.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        if c == delimiter {
+            end = Some(idx + c.len_utf8());
+            break;
+        }
+        if c == '\\' {
+            match chars.next() {
+                Some((_, c)) if c == delimiter => pattern.push(c),
+                Some((_, c)) => {
+                    pattern.push('\\');
+                    pattern.push(c);
+                }
+                None => pattern.push('\\'),
+            }
+        } else {
+            pattern.push(c);
+        }
+    }
+    let rest = match end {
+        Some(end) => &body[end..],
+        None => return Err(ParseCommandError::InvalidArgument(args.into())),
+    };
+    let flags_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let flags = &rest[..flags_end];
+    let files: Vec<String> = rest[flags_end..]
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect();
+    if files.is_empty() {
+        return Err(ParseCommandError::ExpectedArgument(args.into()));
+    }
+    Ok(Command::VimGrep {
+        pattern,
+        files,
+        all: flags.contains('g'),
+        jump: !flags.contains('j'),
+    })
+}
+
 #[derive(Debug)]
 pub enum InputType {
     Command,
-    Search,
+    Search(SearchDirection),
 }
 
 #[derive(Debug)]
@@ -85,15 +286,21 @@ pub struct CommandPrompt {
     index: usize,
     input_type: InputType,
     prefix: String,
+    status: Option<String>,
+    history: Vec<String>,
+    history_index: usize,
 }
 
 impl CommandPrompt {
-    pub fn search() -> Self {
+    pub fn search(direction: SearchDirection, history: Vec<String>) -> Self {
         CommandPrompt {
             chars: "".to_string(),
             index: 0,
-            input_type: InputType::Search,
-            prefix: "/".to_string(),
+            input_type: InputType::Search(direction),
+            prefix: direction.delimiter().to_string(),
+            status: None,
+            history_index: history.len(),
+            history,
         }
     }
 
@@ -103,9 +310,27 @@ impl CommandPrompt {
             index: 0,
             input_type: InputType::Command,
             prefix: ":".to_string(),
+            status: None,
+            history: Vec::new(),
+            history_index: 0,
+        }
+    }
+
+    pub fn search_direction(&self) -> Option<SearchDirection> {
+        match self.input_type {
+            InputType::Search(direction) => Some(direction),
+            InputType::Command => None,
         }
     }
 
+    pub fn text(&self) -> &str {
+        &self.chars
+    }
+
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.status = status;
+    }
+
     pub fn handle_input(&mut self, input: &Event) -> Result<Option<Command>, ParseCommandError> {
         match input {
             Event::Key(Key::Char('\n')) => self.finalize(),
@@ -113,6 +338,8 @@ impl CommandPrompt {
             Event::Key(Key::Delete) => Ok(self.delete()),
             Event::Key(Key::Left) => Ok(self.left()),
             Event::Key(Key::Right) => Ok(self.right()),
+            Event::Key(Key::Up) => Ok(self.history_prev()),
+            Event::Key(Key::Down) => Ok(self.history_next()),
             Event::Key(Key::Char(chr)) => Ok(self.new_key(*chr)),
             _ => Ok(None),
         }
@@ -132,6 +359,28 @@ impl CommandPrompt {
         None
     }
 
+    fn history_prev(&mut self) -> Option<Command> {
+        if self.history_index > 0 {
+            self.history_index -= 1;
+            self.chars = self.history[self.history_index].clone();
+            self.index = self.chars.len();
+        }
+        None
+    }
+
+    fn history_next(&mut self) -> Option<Command> {
+        if self.history_index < self.history.len() {
+            self.history_index += 1;
+            self.chars = self
+                .history
+                .get(self.history_index)
+                .cloned()
+                .unwrap_or_default();
+            self.index = self.chars.len();
+        }
+        None
+    }
+
     fn delete(&mut self) -> Option<Command> {
         if self.index < self.chars.len() {
             self.chars.remove(self.index);
@@ -158,22 +407,20 @@ impl CommandPrompt {
     fn finalize(&mut self) -> Result<Option<Command>, ParseCommandError> {
         match self.input_type {
             InputType::Command => Ok(Some(FromStr::from_str(&self.chars)?)),
-            InputType::Search => Ok(Some(Command::Search(self.chars.clone()))),
+            InputType::Search(direction) => {
+                Ok(Some(Command::Search(self.chars.clone(), direction)))
+            }
         }
     }
 
-    pub fn render<W: Write>(&mut self, w: &mut W, row: u16) -> Result<(), Error> {
-        if let Err(err) = write!(
-            w,
-            "{}{}{}{}{}",
-            Goto(1 as u16, row),
-            CurrentLine,
-            self.prefix,
-            self.chars,
-            Goto(self.prefix.len() as u16 + self.index as u16 + 1, row)
-        ) {
-            error!("{}", err);
-        }
-        Ok(())
+    pub fn render(&mut self, screen: &mut Screen, row: u16) {
+        let status = match self.status {
+            Some(ref status) => format!("  {}", status),
+            None => String::new(),
+        };
+        let text = format!("{}{}{}", self.prefix, self.chars, status);
+        screen.clear_line(0, row);
+        screen.print(0, row, &text, CellStyle::default());
+        screen.set_cursor(self.prefix.len() as u16 + self.index as u16, row);
     }
 }
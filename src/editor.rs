@@ -1,17 +1,48 @@
-use crate::client::Client as ViewClient;
-use crate::view::View;
+use crate::client::{Client as ViewClient, ClientResult};
+use crate::layout::{self, Direction, Layout, Pane, PaneId, Rect, SplitDirection, Tab};
+use crate::marks::{self, FileMark, Jump, JumpList};
+use crate::options::Options;
+use crate::quickfix::{self, QuickfixError, QuickfixList, SearchResult};
+use crate::screen::{CellStyle, Screen};
+use crate::search::{SearchDirection, SearchQuery};
+use crate::swap::{self, Journal};
+use crate::undo;
+use crate::view::{Cursor, FileChange, View};
 use crate::vix::CoreEvent;
+use crate::window::{Scroll, Window};
 use futures::sync::mpsc::UnboundedReceiver;
+use futures::sync::oneshot;
 use futures::{Async, Future, Stream};
+use serde_json::Value;
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
-use std::io::Write;
-use termion::event::Event;
-use tokio;
-use xrl::{Client, ClientResult, ModifySelection, ScrollTo, Style, Update, ViewId};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::mem;
+use termion::event::{Event, MouseEvent};
+use xrl::{Client, FindStatus, ScrollTo, Style, Update, ViewId};
+
+const SEARCH_HISTORY_SIZE: usize = 50;
+
+type OpenRequest = (Option<String>, ClientResult<(ViewId, View)>);
 
 pub struct Editor {
     clipboard: String, // FIXME: Replace this with something better
-    pub pending_open_requests: Vec<ClientResult<(ViewId, View)>>,
+    pending_clipboard_request: Option<ClientResult<Value>>,
+    pending_paste: Option<ViewId>,
+    /// Views being opened, with the file they show.
+    pub pending_open_requests: Vec<OpenRequest>,
+    pending_save_requests: Vec<(ViewId, ClientResult<()>)>,
+    /// Saves sent to xi-core that it did not confirm with a pristine update
+    /// yet, oldest first.
+    sent_saves: Vec<ViewId>,
+    save_failed: bool,
+    pending_reloads: Vec<(String, ViewId)>,
+    recoveries: Vec<(ViewId, Journal)>,
+    jump_list: JumpList,
+    file_marks: HashMap<char, FileMark>,
     pub delayed_events: Vec<CoreEvent>,
     pub views: HashMap<ViewId, View>,
     pub current_view: ViewId,
@@ -19,65 +50,383 @@ pub struct Editor {
     pub client: Client,
     pub size: (u16, u16),
     pub styles: HashMap<u64, Style>,
+    pub options: Options,
+    changed: bool,
+    last_search: Option<SearchQuery>,
+    search_history: Vec<String>,
+    quickfix: QuickfixList,
+    /// A running `:vimgrep` or `:grep`, with the title of its list and
+    /// whether to jump to the first match.
+    pending_search: Option<(String, bool, oneshot::Receiver<SearchResult>)>,
+    pending_jump: Option<(String, u64, u64)>,
+    layout: Layout,
+    panes: HashMap<PaneId, Pane>,
+    rects: Vec<(PaneId, Rect)>,
+    current_pane: PaneId,
+    next_pane_id: PaneId,
+    /// The other tab pages, the current one being at `current_tab`.
+    tabs: Vec<Tab>,
+    current_tab: usize,
+    error: Option<String>,
 }
 
 impl Editor {
     pub fn new(client: Client, events: UnboundedReceiver<CoreEvent>) -> Editor {
         let mut styles = HashMap::new();
         styles.insert(0, Default::default());
+        let file_marks = marks::load_file_marks().unwrap_or_else(|err| {
+            error!("failed to load file marks: {}", err);
+            HashMap::new()
+        });
 
         Editor {
             clipboard: String::default(),
+            pending_clipboard_request: None,
+            pending_paste: None,
             events,
             delayed_events: Vec::new(),
             pending_open_requests: Vec::new(),
+            pending_save_requests: Vec::new(),
+            sent_saves: Vec::new(),
+            save_failed: false,
+            pending_reloads: Vec::new(),
+            recoveries: Vec::new(),
+            jump_list: JumpList::default(),
+            file_marks,
             size: (0, 0),
             views: HashMap::new(),
             styles,
             current_view: ViewId(0),
             client,
+            options: Options::default(),
+            changed: true,
+            last_search: None,
+            search_history: Vec::new(),
+            quickfix: QuickfixList::default(),
+            pending_search: None,
+            pending_jump: None,
+            layout: Layout::Pane(0),
+            panes: HashMap::new(),
+            rects: Vec::new(),
+            current_pane: 0,
+            next_pane_id: 0,
+            tabs: Vec::new(),
+            current_tab: 0,
+            error: None,
         }
     }
 }
 
 impl Editor {
     pub fn handle_input(&mut self, event: Event) {
-        if let Some(view) = self.views.get_mut(&self.current_view) {
-            view.handle_input(event)
+        if let Event::Mouse(MouseEvent::Press(_, x, y)) = event {
+            if let Some(pane_id) = self.pane_at(x.saturating_sub(1), y.saturating_sub(1)) {
+                self.focus_pane(pane_id);
+            }
+        }
+        if let Some(pane) = self.panes.get(&self.current_pane) {
+            if let Some(view) = self.views.get_mut(&pane.view_id) {
+                view.handle_input(event, &pane.window)
+            }
         }
     }
 
     pub fn handle_resize(&mut self, size: (u16, u16)) {
         info!("Setting new terminal size");
-        self.size = (size.0, size.1 - 1);
-        if let Some(view) = self.views.get_mut(&self.current_view) {
-            view.resize(self.size.1)
+        // Keep the first row for the tabline and the last one for the prompt.
+        self.size = (size.0, size.1.saturating_sub(2));
+        self.relayout();
+    }
+
+    fn relayout(&mut self) {
+        // The last row is shared by the status line of the bottom panes and
+        // the command prompt.
+        let area = Rect {
+            x: 0,
+            y: 1,
+            width: self.size.0,
+            height: self.size.1 + 1,
+        };
+        self.rects.clear();
+        self.layout.compute(area, &mut self.rects);
+        for (pane_id, rect) in &self.rects {
+            if let Some(pane) = self.panes.get_mut(pane_id) {
+                pane.window.set_rect(*rect);
+                if let Some(view) = self.views.get_mut(&pane.view_id) {
+                    view.resize(&mut pane.window);
+                }
+            }
+        }
+    }
+
+    fn pane_at(&self, x: u16, y: u16) -> Option<PaneId> {
+        self.rects
+            .iter()
+            .find(|(_, rect)| rect.contains(x, y))
+            .map(|(pane_id, _)| *pane_id)
+    }
+}
+
+impl Editor {
+    fn show_view(&mut self, view_id: ViewId) {
+        let cursor = match self.views.get(&view_id) {
+            Some(view) => view.cursor().clone(),
+            None => return,
+        };
+        if self.panes.is_empty() {
+            let pane_id = self.next_pane_id;
+            self.next_pane_id += 1;
+            self.panes
+                .insert(pane_id, Pane::new(view_id, Window::new()));
+            self.layout = Layout::Pane(pane_id);
+            self.current_pane = pane_id;
+        }
+        if let Some(pane) = self.panes.get_mut(&self.current_pane) {
+            pane.view_id = view_id;
+            pane.window.set_cursor(&cursor);
+        }
+        self.current_view = view_id;
+        self.relayout();
+    }
+
+    pub fn focus_pane(&mut self, pane_id: PaneId) {
+        let (view_id, cursor) = match self.panes.get(&pane_id) {
+            Some(pane) => (pane.view_id, pane.window.get_cursor()),
+            None => return,
+        };
+        self.current_pane = pane_id;
+        self.current_view = view_id;
+        // A xi view has a single cursor, move it to where this pane left it.
+        if let Some(view) = self.views.get_mut(&view_id) {
+            if *view.cursor() != cursor {
+                view.goto(cursor.line, cursor.column);
+            }
+        }
+    }
+
+    pub fn focus_direction(&mut self, direction: Direction) {
+        if let Some(pane_id) = layout::neighbor(&self.rects, self.current_pane, direction) {
+            self.focus_pane(pane_id);
+        }
+    }
+
+    pub fn focus_next(&mut self, forward: bool) {
+        let panes = self.layout.panes();
+        if let Some(idx) = panes
+            .iter()
+            .position(|pane_id| *pane_id == self.current_pane)
+        {
+            let next = if forward {
+                (idx + 1) % panes.len()
+            } else {
+                (idx + panes.len() - 1) % panes.len()
+            };
+            self.focus_pane(panes[next]);
+        }
+    }
+
+    pub fn split(&mut self, direction: SplitDirection, file: Option<String>) {
+        let pane = match self.panes.get(&self.current_pane) {
+            Some(pane) => Pane::new(pane.view_id, pane.window.clone()),
+            None => return,
+        };
+        let pane_id = self.next_pane_id;
+        self.next_pane_id += 1;
+        self.panes.insert(pane_id, pane);
+        self.layout.split(self.current_pane, pane_id, direction);
+        self.focus_pane(pane_id);
+        self.relayout();
+        if let Some(file) = file {
+            self.edit(&file);
+        }
+    }
+
+    /// Closes the current pane, or the tab page if it is its last pane.
+    /// Returns false for the last pane of the last tab page.
+    pub fn close_pane(&mut self) -> bool {
+        if self.panes.len() < 2 {
+            return self.close_tab();
+        }
+        let panes = self.layout.panes();
+        let idx = panes
+            .iter()
+            .position(|pane_id| *pane_id == self.current_pane)
+            .unwrap_or(0);
+        self.layout.remove(self.current_pane);
+        self.panes.remove(&self.current_pane);
+        let panes = self.layout.panes();
+        self.focus_pane(panes[idx.min(panes.len() - 1)]);
+        self.relayout();
+        true
+    }
+
+    pub fn only_pane(&mut self) {
+        let current = self.current_pane;
+        self.panes.retain(|pane_id, _| *pane_id == current);
+        self.layout = Layout::Pane(current);
+        self.relayout();
+    }
+
+    pub fn equalize_panes(&mut self) {
+        self.layout.equalize();
+        self.relayout();
+    }
+
+    pub fn resize_pane(&mut self, direction: SplitDirection, delta: i32) {
+        self.layout.resize(self.current_pane, direction, delta);
+        self.relayout();
+    }
+}
+
+impl Editor {
+    pub fn tab_count(&self) -> usize {
+        self.tabs.len() + 1
+    }
+
+    fn take_tab(&mut self) -> Tab {
+        Tab {
+            layout: mem::replace(&mut self.layout, Layout::Pane(0)),
+            panes: mem::take(&mut self.panes),
+            current_pane: self.current_pane,
+        }
+    }
+
+    fn load_tab(&mut self, tab: Tab) {
+        self.layout = tab.layout;
+        self.panes = tab.panes;
+        self.focus_pane(tab.current_pane);
+        self.relayout();
+    }
+
+    pub fn new_tab(&mut self, file: Option<String>) {
+        let tab = self.take_tab();
+        self.tabs.insert(self.current_tab, tab);
+        self.current_tab += 1;
+        self.rects.clear();
+        match file {
+            Some(file) => self.edit(&file),
+            None => self.open(None),
+        }
+    }
+
+    pub fn goto_tab(&mut self, idx: usize) {
+        if idx == self.current_tab || idx >= self.tab_count() {
+            return;
+        }
+        let tab = self.take_tab();
+        self.tabs.insert(self.current_tab, tab);
+        let tab = self.tabs.remove(idx);
+        self.current_tab = idx;
+        self.load_tab(tab);
+    }
+
+    pub fn next_tab(&mut self, forward: bool) {
+        let count = self.tab_count();
+        let idx = if forward {
+            (self.current_tab + 1) % count
         } else {
-            warn!("View {} not found", self.current_view);
+            (self.current_tab + count - 1) % count
+        };
+        self.goto_tab(idx);
+    }
+
+    pub fn close_tab(&mut self) -> bool {
+        if self.tabs.is_empty() {
+            return false;
         }
+        let idx = cmp::min(self.current_tab, self.tabs.len() - 1);
+        let tab = self.tabs.remove(idx);
+        self.current_tab = idx;
+        self.load_tab(tab);
+        true
+    }
+
+    pub fn only_tab(&mut self) {
+        self.tabs.clear();
+        self.current_tab = 0;
     }
 }
 
 impl Editor {
     pub fn dispatch_core_event(&mut self, event: CoreEvent) {
+        self.changed = true;
         match event {
             CoreEvent::Update(update) => self.handle_update(update),
             CoreEvent::SetStyle(style) => self.handle_def_style(style),
             CoreEvent::ScrollTo(scroll_to) => self.handle_scroll_to(scroll_to),
+            CoreEvent::FindStatus(status) => self.handle_find_status(status),
+            CoreEvent::Alert(alert) => {
+                error!("xi-core alert: {}", alert.msg);
+                // xi-core handles saves in order and reports a failed one
+                // with an alert, so it is about the oldest one it did not
+                // confirm yet.
+                if self.sent_saves.is_empty() {
+                    self.error = Some(alert.msg);
+                } else {
+                    let view_id = self.sent_saves.remove(0);
+                    self.finish_save(view_id, Err(alert.msg));
+                }
+            }
         }
     }
 
     fn handle_update(&mut self, update: Update) {
-        match self.views.get_mut(&update.view_id) {
-            Some(view) => view.update_cache(update),
-            None => self.delayed_events.push(CoreEvent::Update(update)),
+        let view_id = update.view_id;
+        let pristine = update.pristine;
+        match self.views.get_mut(&view_id) {
+            Some(view) => {
+                self.jump_list.update(view_id, &update.operations);
+                for mark in self.file_marks.values_mut() {
+                    if mark.view_id == Some(view_id) {
+                        mark.line = marks::map_line(&update.operations, mark.line);
+                    }
+                }
+                view.update_cache(update)
+            }
+            None => {
+                self.delayed_events.push(CoreEvent::Update(update));
+                return;
+            }
+        }
+        // xi-core marks the buffer pristine once the file is written.
+        if pristine {
+            if let Some(idx) = self.sent_saves.iter().position(|id| *id == view_id) {
+                self.sent_saves.remove(idx);
+                self.finish_save(view_id, Ok(()));
+            }
         }
     }
 
     fn handle_scroll_to(&mut self, scroll_to: ScrollTo) {
-        match self.views.get_mut(&scroll_to.view_id) {
+        let view_id = scroll_to.view_id;
+        match self.views.get_mut(&view_id) {
             Some(view) => view.set_cursor(scroll_to.line, scroll_to.column),
-            None => self.delayed_events.push(CoreEvent::ScrollTo(scroll_to)),
+            None => {
+                self.delayed_events.push(CoreEvent::ScrollTo(scroll_to));
+                return;
+            }
+        }
+        // Only the focused pane follows the cursor, unless the view is not
+        // shown there.
+        let cursor = Cursor {
+            line: scroll_to.line,
+            column: scroll_to.column,
+        };
+        let focused = self
+            .panes
+            .get(&self.current_pane)
+            .is_some_and(|pane| pane.view_id == view_id);
+        for (pane_id, pane) in self.panes.iter_mut() {
+            if pane.view_id == view_id && (!focused || *pane_id == self.current_pane) {
+                pane.window.set_cursor(&cursor);
+            }
+        }
+    }
+
+    fn handle_find_status(&mut self, status: FindStatus) {
+        match self.views.get_mut(&status.view_id) {
+            Some(view) => view.set_find_status(status),
+            None => self.delayed_events.push(CoreEvent::FindStatus(status)),
         }
     }
 
@@ -89,6 +438,7 @@ impl Editor {
 impl Editor {
     pub fn open(&mut self, file_path: Option<String>) {
         let client = self.client.clone();
+        let file = file_path.clone();
         let task = self
             .client
             .new_view(file_path.clone())
@@ -99,10 +449,131 @@ impl Editor {
                     View::new(view_client, Some(file_path.unwrap_or_else(|| "".into()))),
                 ))
             });
-        self.pending_open_requests.push(Box::new(task));
+        self.pending_open_requests.push((file, Box::new(task)));
+    }
+
+    pub fn edit(&mut self, file: &str) {
+        self.push_jump();
+        match self.find_view(file) {
+            Some(view_id) => self.show_view(view_id),
+            None => self.open(Some(file.to_owned())),
+        }
+    }
+
+    pub fn jump_to(&mut self, file: &str, line: u64, column: u64) {
+        self.push_jump();
+        match self.find_view(file) {
+            Some(view_id) => {
+                self.show_view(view_id);
+                if let Some(view) = self.views.get_mut(&view_id) {
+                    view.goto(line, column);
+                }
+            }
+            None => {
+                self.pending_jump = Some((file.to_owned(), line, column));
+                self.open(Some(file.to_owned()));
+            }
+        }
+    }
+
+    pub fn reload(&mut self, view_id: ViewId) {
+        let file = match self.views.get(&view_id).and_then(View::file) {
+            Some(file) => file.to_owned(),
+            None => return,
+        };
+        info!("reloading {}", file);
+        // xi-core cannot reload a buffer, so the view is replaced by a new one.
+        tokio::spawn(self.client.close_view(view_id).map_err(|_| ()));
+        self.pending_reloads.push((file.clone(), view_id));
+        self.open(Some(file));
+    }
+
+    fn replace_view(&mut self, old_id: ViewId, view_id: ViewId, mut view: View) {
+        if let Some(old) = self.views.remove(&old_id) {
+            let cursor = old.cursor().clone();
+            view.goto(cursor.line, cursor.column);
+        }
+        self.views.insert(view_id, view);
+        self.jump_list.replace_view(old_id, view_id);
+        for mark in self.file_marks.values_mut() {
+            if mark.view_id == Some(old_id) {
+                mark.view_id = Some(view_id);
+            }
+        }
+        let tabs = self.tabs.iter_mut().map(|tab| &mut tab.panes);
+        for panes in tabs.chain(Some(&mut self.panes)) {
+            for pane in panes.values_mut() {
+                if pane.view_id == old_id {
+                    pane.view_id = view_id;
+                }
+            }
+        }
+        if self.current_view == old_id {
+            self.current_view = view_id;
+        }
+        self.relayout();
+    }
+
+    /// Checks whether open files were changed by another program, reloading
+    /// unmodified buffers if `autoread` is set. Returns a warning for the
+    /// first file that needs the user to decide.
+    pub fn check_files(&mut self) -> Option<(ViewId, String)> {
+        let mut reload = vec![];
+        let mut conflict = None;
+        for (view_id, view) in self.views.iter_mut() {
+            let saving = self
+                .pending_save_requests
+                .iter()
+                .map(|(saving, _)| saving)
+                .chain(&self.sent_saves)
+                .any(|saving| saving == view_id);
+            if saving || self.pending_reloads.iter().any(|(_, id)| id == view_id) {
+                continue;
+            }
+            match view.check_disk_state() {
+                FileChange::Unchanged => {}
+                FileChange::Deleted => {
+                    self.error = Some(format!("File \"{}\" no longer available", view.name()));
+                }
+                FileChange::Changed if !view.is_modified() && self.options.autoread => {
+                    reload.push(*view_id);
+                }
+                FileChange::Changed => {
+                    let warning = if view.is_modified() {
+                        "has changed and the buffer was changed in vix as well"
+                    } else {
+                        "has changed since editing started"
+                    };
+                    conflict = Some((
+                        *view_id,
+                        format!(
+                            "Warning: File \"{}\" {}  [O]K, (L)oad File",
+                            view.name(),
+                            warning
+                        ),
+                    ));
+                    break;
+                }
+            }
+        }
+        for view_id in reload {
+            self.reload(view_id);
+        }
+        conflict
+    }
+
+    fn find_view(&self, file: &str) -> Option<ViewId> {
+        self.views
+            .iter()
+            .find(|(_, view)| view.is_file(file))
+            .map(|(view_id, _)| *view_id)
     }
 
     pub fn paste(&mut self) {
+        if self.pending_clipboard_request.is_some() {
+            self.pending_paste = Some(self.current_view);
+            return;
+        }
         if let Some(view) = self.views.get_mut(&self.current_view) {
             view.paste(&self.clipboard);
         }
@@ -110,34 +581,43 @@ impl Editor {
 
     pub fn copy(&mut self) {
         if let Some(view) = self.views.get_mut(&self.current_view) {
-            match view.copy().wait() {
-                Ok(value) => {
-                    self.clipboard = match value.as_str() {
-                        Some(value) => String::from(value),
-                        None => {
-                            error!("could not copy clipboard");
-                            String::default()
-                        }
-                    };
-                }
-                Err(err) => error!("error copying: {}", err),
-            }
+            self.pending_clipboard_request = Some(view.copy());
         }
     }
 
     pub fn cut(&mut self) {
         if let Some(view) = self.views.get_mut(&self.current_view) {
-            match view.cut().wait() {
-                Ok(value) => {
-                    self.clipboard = match value.as_str() {
-                        Some(value) => String::from(value),
-                        None => {
-                            error!("could not copy clipboard");
-                            String::default()
-                        }
-                    };
-                }
-                Err(err) => error!("error copying: {}", err),
+            self.pending_clipboard_request = Some(view.cut());
+        }
+    }
+
+    pub fn process_clipboard_request(&mut self) {
+        let result = match self
+            .pending_clipboard_request
+            .as_mut()
+            .map(|request| request.poll())
+        {
+            Some(Ok(Async::Ready(value))) => Ok(value),
+            Some(Ok(Async::NotReady)) | None => return,
+            Some(Err(err)) => Err(err),
+        };
+        self.pending_clipboard_request = None;
+        self.changed = true;
+        match result {
+            Ok(value) => {
+                self.clipboard = match value.as_str() {
+                    Some(value) => String::from(value),
+                    None => {
+                        error!("could not copy clipboard");
+                        String::default()
+                    }
+                };
+            }
+            Err(err) => error!("error copying: {}", err),
+        }
+        if let Some(view_id) = self.pending_paste.take() {
+            if let Some(view) = self.views.get_mut(&view_id) {
+                view.paste(&self.clipboard);
             }
         }
     }
@@ -154,49 +634,288 @@ impl Editor {
         }
     }
 
-    pub fn find(
-        &mut self,
-        search_term: &str,
-        case_sensitive: bool,
-        regex: bool,
-        whole_words: bool,
-    ) {
+    pub fn undo_step(&mut self, count: i64) {
         if let Some(view) = self.views.get_mut(&self.current_view) {
-            view.find(search_term, case_sensitive, regex, whole_words);
+            view.undo_step(count);
         }
     }
 
-    pub fn find_next(
-        &mut self,
-        wrap_around: bool,
-        allow_same: bool,
-        modify_selection: ModifySelection,
-    ) {
+    pub fn undo_travel(&mut self, seconds: i64) {
         if let Some(view) = self.views.get_mut(&self.current_view) {
-            view.find_next(wrap_around, allow_same, modify_selection);
+            view.undo_travel(seconds);
         }
     }
 
-    pub fn find_prev(
-        &mut self,
-        wrap_around: bool,
-        allow_same: bool,
-        modify_selection: ModifySelection,
-    ) {
+    /// Whether something to render changed since the last call, other than
+    /// through the commands vix runs.
+    pub fn take_changed(&mut self) -> bool {
+        mem::replace(&mut self.changed, false)
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.views.values().any(View::is_replaying)
+    }
+
+    /// Ends the undo commands whose changes were made.
+    pub fn process_replays(&mut self) {
+        let done: Vec<ViewId> = self
+            .views
+            .iter_mut()
+            .filter(|(_, view)| view.is_replaying())
+            .filter_map(|(id, view)| if view.poll_replay() { Some(*id) } else { None })
+            .collect();
+        if done.is_empty() {
+            return;
+        }
+        // The updates for the changes come before the response that ends
+        // them, and must not be recorded.
+        self.process_core_events();
+        for id in done {
+            if let Some(view) = self.views.get_mut(&id) {
+                view.finish_replay();
+            }
+        }
+    }
+
+    pub fn replace_char(&mut self, chr: char) {
         if let Some(view) = self.views.get_mut(&self.current_view) {
-            view.find_prev(wrap_around, allow_same, modify_selection);
+            view.replace_char(chr);
         }
     }
 
-    pub fn find_all(&mut self) {
+    pub fn checkpoint(&mut self) {
+        for view in self.views.values_mut() {
+            view.checkpoint();
+        }
+    }
+
+    /// Runs the search typed after `/` or `?`, reusing the last search term
+    /// if `pattern` has none of its own.
+    pub fn search(&mut self, pattern: &str, direction: SearchDirection) {
+        let query = SearchQuery::parse(pattern, direction, &self.options);
+        let query = match self.last_search {
+            Some(ref last) if query.term.is_empty() => SearchQuery {
+                term: last.term.clone(),
+                case_sensitive: last.case_sensitive,
+                regex: last.regex,
+                whole_words: last.whole_words,
+                ..query
+            },
+            _ => query,
+        };
+        let origin = self
+            .views
+            .get(&self.current_view)
+            .and_then(|view| view.search_origin().cloned());
+        match origin {
+            Some(cursor) => self.jump_list.push(Jump {
+                view_id: self.current_view,
+                cursor,
+            }),
+            None => self.push_jump(),
+        }
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.search(&query, self.options.wrapscan, true);
+        }
+        self.add_search_history(pattern);
+        self.last_search = Some(query);
+    }
+
+    pub fn search_word(&mut self, direction: SearchDirection, whole_words: bool) {
+        let word = match self.current_word() {
+            Some(word) => word,
+            None => return,
+        };
+        let mut query = SearchQuery::word(&word, direction, &self.options);
+        query.whole_words = whole_words;
+        self.push_jump();
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.search(&query, self.options.wrapscan, false);
+        }
+        self.add_search_history(&word);
+        self.last_search = Some(query);
+    }
+
+    pub fn goto_declaration(&mut self, global: bool) {
+        let word = match self.current_word() {
+            Some(word) => word,
+            None => return,
+        };
+        let mut query = SearchQuery::word(&word, SearchDirection::Forward, &self.options);
+        query.case_sensitive = true;
+        self.push_jump();
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.goto_declaration(&query, global);
+        }
+        self.add_search_history(&word);
+        self.last_search = Some(query);
+    }
+
+    fn current_word(&self) -> Option<String> {
+        self.views
+            .get(&self.current_view)
+            .and_then(|view| view.word_under_cursor())
+    }
+
+    fn add_search_history(&mut self, pattern: &str) {
+        if pattern.is_empty() {
+            return;
+        }
+        self.search_history.retain(|entry| entry != pattern);
+        self.search_history.push(pattern.to_owned());
+        if self.search_history.len() > SEARCH_HISTORY_SIZE {
+            self.search_history.remove(0);
+        }
+    }
+
+    pub fn search_history(&self) -> Vec<String> {
+        self.search_history.clone()
+    }
+
+    pub fn search_next(&mut self, reverse: bool) {
+        if self.last_search.is_some() {
+            self.push_jump();
+        }
+        if let (Some(query), Some(view)) = (
+            self.last_search.as_ref(),
+            self.views.get_mut(&self.current_view),
+        ) {
+            view.search_next(query, reverse, self.options.wrapscan);
+        }
+    }
+
+    pub fn incremental_find(&mut self, pattern: &str, direction: SearchDirection) {
+        let query = SearchQuery::parse(pattern, direction, &self.options);
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.incremental_find(&query, self.options.wrapscan);
+        }
+    }
+
+    pub fn save_search_origin(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.save_search_origin();
+        }
+    }
+
+    pub fn restore_search_origin(&mut self) {
         if let Some(view) = self.views.get_mut(&self.current_view) {
-            view.find_all();
+            view.restore_search_origin();
         }
     }
 
-    pub fn highlight_find(&mut self, visible: bool) {
+    pub fn clear_search_origin(&mut self) {
         if let Some(view) = self.views.get_mut(&self.current_view) {
-            view.highlight_find(visible);
+            view.clear_search_origin();
+        }
+    }
+
+    pub fn clear_message(&mut self) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.clear_message();
+        }
+    }
+
+    pub fn take_error(&mut self) -> Option<String> {
+        if let Some(error) = self.error.take() {
+            return Some(error);
+        }
+        self.views
+            .get_mut(&self.current_view)
+            .and_then(|view| view.take_error())
+    }
+
+    pub fn search_status(&self) -> Option<String> {
+        self.views
+            .get(&self.current_view)
+            .and_then(|view| view.search_matches())
+            .map(|(current, total)| format!("[{}/{}]", current, total))
+    }
+
+    pub fn vimgrep(&mut self, pattern: &str, files: &[String], all: bool, jump: bool) {
+        let title = format!(":vimgrep /{}/", pattern);
+        let (pattern, files) = (pattern.to_owned(), files.to_vec());
+        let search = quickfix::spawn(move || quickfix::vimgrep(&pattern, &files, all));
+        self.pending_search = Some((title, jump, search));
+    }
+
+    pub fn grep(&mut self, args: &str) {
+        let title = format!(":grep {}", args);
+        let (program, args) = (self.options.grepprg.clone(), args.to_owned());
+        let search = quickfix::spawn(move || quickfix::grep(&program, &args));
+        self.pending_search = Some((title, true, search));
+    }
+
+    pub fn process_search(&mut self) {
+        let result = match self.pending_search {
+            Some((_, _, ref mut search)) => match search.poll() {
+                Ok(Async::Ready(result)) => result,
+                Ok(Async::NotReady) => return,
+                Err(_) => Err(QuickfixError::Io(io::Error::other("search aborted"))),
+            },
+            None => return,
+        };
+        let (title, jump, _) = match self.pending_search.take() {
+            Some(search) => search,
+            None => return,
+        };
+        self.changed = true;
+        match result {
+            Ok(entries) => {
+                self.quickfix = QuickfixList::new(title, entries);
+                if jump {
+                    self.quickfix_jump();
+                }
+            }
+            Err(err) => self.error = Some(err.to_string()),
+        }
+    }
+
+    pub fn quickfix_jump(&mut self) {
+        if let Some(entry) = self.quickfix.current().cloned() {
+            self.jump_to(&entry.file, entry.line, entry.column);
+        }
+    }
+
+    pub fn quickfix_next(&mut self) -> Result<(), QuickfixError> {
+        self.check_quickfix()?;
+        self.quickfix.next().ok_or(QuickfixError::NoMoreItems)?;
+        self.quickfix_jump();
+        Ok(())
+    }
+
+    pub fn quickfix_prev(&mut self) -> Result<(), QuickfixError> {
+        self.check_quickfix()?;
+        self.quickfix.prev().ok_or(QuickfixError::NoMoreItems)?;
+        self.quickfix_jump();
+        Ok(())
+    }
+
+    /// Jumps to entry `nr`, counting from one, or to the current entry.
+    pub fn quickfix_goto(&mut self, nr: Option<usize>) -> Result<(), QuickfixError> {
+        self.check_quickfix()?;
+        if let Some(nr) = nr {
+            self.quickfix
+                .select(nr.saturating_sub(1))
+                .ok_or(QuickfixError::NoMoreItems)?;
+        }
+        self.quickfix_jump();
+        Ok(())
+    }
+
+    pub fn quickfix_select(&mut self, forward: bool) {
+        let index = self.quickfix.index();
+        if forward {
+            self.quickfix.select(index + 1);
+        } else if index > 0 {
+            self.quickfix.select(index - 1);
+        }
+    }
+
+    pub fn check_quickfix(&self) -> Result<(), QuickfixError> {
+        if self.quickfix.is_empty() {
+            Err(QuickfixError::NoErrors)
+        } else {
+            Ok(())
         }
     }
 
@@ -230,20 +949,191 @@ impl Editor {
     }
 
     pub fn goto_line(&mut self, line: u64) {
+        self.push_jump();
         if let Some(view) = self.views.get_mut(&self.current_view) {
             view.goto_line(line);
         }
     }
 
+    pub fn goto_last_line(&mut self) {
+        let last = self
+            .views
+            .get(&self.current_view)
+            .map(|view| view.line_count().saturating_sub(1));
+        if let Some(line) = last {
+            self.goto_line(line);
+        }
+    }
+}
+
+impl Editor {
+    fn current_position(&self) -> Option<Jump> {
+        self.views.get(&self.current_view).map(|view| Jump {
+            view_id: self.current_view,
+            cursor: view.cursor().clone(),
+        })
+    }
+
+    pub fn push_jump(&mut self) {
+        if let Some(jump) = self.current_position() {
+            self.jump_list.push(jump);
+        }
+    }
+
+    fn goto_position(&mut self, jump: Jump) {
+        if jump.view_id != self.current_view {
+            self.show_view(jump.view_id);
+        }
+        if let Some(view) = self.views.get_mut(&jump.view_id) {
+            view.goto(jump.cursor.line, jump.cursor.column);
+        }
+    }
+
+    pub fn jump_back(&mut self) {
+        let from = match self.current_position() {
+            Some(from) => from,
+            None => return,
+        };
+        if let Some(jump) = self.jump_list.back(from) {
+            self.goto_position(jump);
+        }
+    }
+
+    pub fn jump_forward(&mut self) {
+        if let Some(jump) = self.jump_list.forward() {
+            self.goto_position(jump);
+        }
+    }
+
+    pub fn set_mark(&mut self, mark: char) {
+        let view = match self.views.get_mut(&self.current_view) {
+            Some(view) => view,
+            None => return,
+        };
+        if mark.is_ascii_lowercase() {
+            view.set_mark(mark);
+        } else if let Some(file) = view.file() {
+            let cursor = view.cursor();
+            self.file_marks.insert(
+                mark,
+                FileMark {
+                    file: file.to_owned(),
+                    line: cursor.line,
+                    column: cursor.column,
+                    view_id: Some(self.current_view),
+                },
+            );
+            self.store_file_marks();
+        }
+    }
+
+    /// Jumps to a mark, to its line only for `'x` or to the exact position
+    /// for `` `x ``. `'` and `` ` `` are the position before the last jump.
+    pub fn goto_mark(&mut self, mark: char, exact: bool) {
+        let target = match mark {
+            '\'' | '`' => self
+                .jump_list
+                .last()
+                .map(|jump| (jump.view_id, None, jump.cursor.clone())),
+            '.' => self.views.get(&self.current_view).and_then(|view| {
+                view.last_change()
+                    .map(|cursor| (self.current_view, None, cursor.clone()))
+            }),
+            'a'..='z' => self.views.get(&self.current_view).and_then(|view| {
+                view.mark(mark)
+                    .map(|cursor| (self.current_view, None, cursor.clone()))
+            }),
+            'A'..='Z' => self.file_marks.get(&mark).map(|file_mark| {
+                let cursor = Cursor {
+                    line: file_mark.line,
+                    column: file_mark.column,
+                };
+                let view_id = file_mark.view_id.unwrap_or(self.current_view);
+                (view_id, Some(file_mark.file.clone()), cursor)
+            }),
+            _ => None,
+        };
+        let (view_id, file, mut cursor) = match target {
+            Some(target) => target,
+            None => {
+                self.error = Some("Mark not set".to_owned());
+                return;
+            }
+        };
+        let view = self.views.get(&view_id);
+        let open = match file {
+            Some(ref file) => view.is_some_and(|view| view.is_file(file)),
+            None => view.is_some(),
+        };
+        if !exact {
+            cursor.column = match view {
+                Some(view) if open => view.first_non_blank(cursor.line),
+                _ => 0,
+            };
+        }
+        match file {
+            // The file of a file mark is opened if needed.
+            Some(ref file) if !open => self.jump_to(file, cursor.line, cursor.column),
+            _ => {
+                self.push_jump();
+                self.goto_position(Jump { view_id, cursor });
+            }
+        }
+    }
+
+    pub fn goto_change(&mut self, older: bool) {
+        if let Some(view) = self.views.get_mut(&self.current_view) {
+            view.goto_change(older);
+        }
+    }
+
+    pub fn store_file_marks(&self) {
+        if let Err(err) = marks::store_file_marks(&self.file_marks) {
+            error!("failed to store file marks: {}", err);
+        }
+    }
+
+    pub fn move_display_line(&mut self, down: bool) {
+        if let Some(pane) = self.panes.get(&self.current_pane) {
+            if let Some(view) = self.views.get_mut(&pane.view_id) {
+                view.move_display_line(&pane.window, down);
+            }
+        }
+    }
+
+    pub fn scroll(&mut self, scroll: Scroll) {
+        if let Some(pane) = self.panes.get_mut(&self.current_pane) {
+            if let Some(view) = self.views.get_mut(&pane.view_id) {
+                view.scroll(&mut pane.window, scroll);
+            }
+        }
+    }
+
+    pub fn scroll_horizontal(&mut self, columns: i64) {
+        if let Some(pane) = self.panes.get_mut(&self.current_pane) {
+            if let Some(view) = self.views.get_mut(&pane.view_id) {
+                view.scroll_horizontal(&mut pane.window, columns);
+            }
+        }
+    }
+
+    pub fn scroll_cursor_to_edge(&mut self, left_edge: bool) {
+        if let Some(pane) = self.panes.get_mut(&self.current_pane) {
+            if let Some(view) = self.views.get_mut(&pane.view_id) {
+                view.scroll_cursor_to_edge(&mut pane.window, left_edge);
+            }
+        }
+    }
+
     pub fn down(&mut self) {
         if self.views.contains_key(&self.current_view) {
-            self.client.down(self.current_view);
+            tokio::spawn(self.client.down(self.current_view).map_err(|_| ()));
         }
     }
 
     pub fn up(&mut self) {
         if self.views.contains_key(&self.current_view) {
-            self.client.up(self.current_view);
+            tokio::spawn(self.client.up(self.current_view).map_err(|_| ()));
         }
     }
 
@@ -255,76 +1145,119 @@ impl Editor {
 
     pub fn right(&mut self) {
         if self.views.contains_key(&self.current_view) {
-            self.client.right(self.current_view);
+            tokio::spawn(self.client.right(self.current_view).map_err(|_| ()));
         }
     }
 
     pub fn select_down(&mut self) {
         if self.views.contains_key(&self.current_view) {
-            self.client.down_sel(self.current_view);
+            tokio::spawn(self.client.down_sel(self.current_view).map_err(|_| ()));
         }
     }
 
     pub fn select_up(&mut self) {
         if self.views.contains_key(&self.current_view) {
-            self.client.up_sel(self.current_view);
+            tokio::spawn(self.client.up_sel(self.current_view).map_err(|_| ()));
         }
     }
 
     pub fn select_left(&mut self) {
         if self.views.contains_key(&self.current_view) {
-            self.client.left_sel(self.current_view);
+            tokio::spawn(self.client.left_sel(self.current_view).map_err(|_| ()));
         }
     }
 
     pub fn select_right(&mut self) {
         if self.views.contains_key(&self.current_view) {
-            self.client.right_sel(self.current_view);
+            tokio::spawn(self.client.right_sel(self.current_view).map_err(|_| ()));
         }
     }
 
     pub fn select_page_up(&mut self) {
         if self.views.contains_key(&self.current_view) {
-            self.client.page_up_sel(self.current_view);
+            tokio::spawn(self.client.page_up_sel(self.current_view).map_err(|_| ()));
         }
     }
 
     pub fn select_page_down(&mut self) {
         if self.views.contains_key(&self.current_view) {
-            self.client.page_down_sel(self.current_view);
+            tokio::spawn(self.client.page_down_sel(self.current_view).map_err(|_| ()));
         }
     }
 
     pub fn select_home(&mut self) {
         if self.views.contains_key(&self.current_view) {
-            self.client.line_start_sel(self.current_view);
+            tokio::spawn(
+                self.client
+                    .line_start_sel(self.current_view)
+                    .map_err(|_| ()),
+            );
         }
     }
 
     pub fn select_end(&mut self) {
         if self.views.contains_key(&self.current_view) {
-            self.client.line_end_sel(self.current_view);
+            tokio::spawn(self.client.line_end_sel(self.current_view).map_err(|_| ()));
         }
     }
 
     pub fn save(&mut self, view: Option<ViewId>) {
-        match view {
-            Some(view_id) => {
-                if let Some(view) = self.views.get_mut(&view_id) {
-                    if let Err(err) = view.save().wait() {
-                        error!("could not save view: {}", err);
-                    }
-                }
-            }
-            None => {
-                if let Some(view) = self.views.get_mut(&self.current_view) {
-                    if let Err(err) = view.save().wait() {
-                        error!("could not save view: {}", err);
-                    }
-                }
+        let view_id = view.unwrap_or(self.current_view);
+        if let Some(view) = self.views.get_mut(&view_id) {
+            match view.save() {
+                Some(task) => self.pending_save_requests.push((view_id, task)),
+                None => self.error = Some("No file name".to_owned()),
             }
         }
     }
+
+    pub fn has_pending_saves(&self) -> bool {
+        !self.pending_save_requests.is_empty() || !self.sent_saves.is_empty()
+    }
+
+    pub fn save_modified(&mut self) {
+        let modified = self
+            .views
+            .get(&self.current_view)
+            .is_some_and(View::is_modified);
+        if modified {
+            self.save(None);
+        }
+    }
+
+    pub fn save_all(&mut self) {
+        let view_ids: Vec<ViewId> = self
+            .views
+            .iter()
+            .filter(|(_, view)| view.is_modified())
+            .map(|(view_id, _)| *view_id)
+            .collect();
+        for view_id in view_ids {
+            self.save(Some(view_id));
+        }
+    }
+
+    /// Returns the name of a buffer with unsaved changes that is not being
+    /// saved, preferring the current one.
+    pub fn modified_buffer(&self) -> Option<String> {
+        let unsaved = |view_id: &ViewId, view: &View| {
+            view.is_modified()
+                && !self
+                    .pending_save_requests
+                    .iter()
+                    .map(|(saving, _)| saving)
+                    .chain(&self.sent_saves)
+                    .any(|saving| saving == view_id)
+        };
+        match self.views.get(&self.current_view) {
+            Some(view) if unsaved(&self.current_view, view) => Some(view.name()),
+            _ => self
+                .views
+                .iter()
+                .find(|(view_id, view)| unsaved(view_id, view))
+                .map(|(_, view)| view.name()),
+        }
+    }
 }
 
 impl Editor {
@@ -336,21 +1269,72 @@ impl Editor {
         info!("process pending open requests");
 
         let mut done = vec![];
-        for (idx, task) in self.pending_open_requests.iter_mut().enumerate() {
+        let mut opened = vec![];
+        let mut failed = vec![];
+        for (idx, (file, task)) in self.pending_open_requests.iter_mut().enumerate() {
             match task.poll() {
-                Ok(Async::Ready((id, mut view))) => {
+                Ok(Async::Ready((id, view))) => {
                     info!("open request succeeded for {}", &id);
                     done.push(idx);
-                    view.resize(self.size.1);
-                    self.views.insert(id, view);
-                    self.current_view = id;
+                    opened.push((id, view));
                 }
                 Ok(Async::NotReady) => continue,
-                Err(e) => panic!("\"open\" task failed: {}", e),
+                Err(err) => {
+                    let file = file.clone().unwrap_or_default();
+                    error!("could not open {}: {}", file, err);
+                    done.push(idx);
+                    failed.push((file, err.to_string()));
+                }
             }
         }
         for idx in done.iter().rev() {
-            self.pending_open_requests.remove(*idx);
+            drop(self.pending_open_requests.remove(*idx));
+            self.changed = true;
+        }
+        for (file, err) in failed {
+            // A view that failed to reload keeps showing the old text.
+            self.pending_reloads
+                .retain(|(reloaded, _)| *reloaded != file);
+            self.error = Some(format!("Could not open \"{}\": {}", file, err));
+        }
+        for (id, mut view) in opened {
+            if let Some(file) = view.file().map(str::to_owned) {
+                if self.options.undofile {
+                    match undo::load(&file) {
+                        Ok(Some(history)) => view.set_undo_history(history),
+                        Ok(None) => {}
+                        Err(err) => error!("failed to load undo history of {}: {}", file, err),
+                    }
+                }
+                match swap::read(&file) {
+                    Ok(Some(journal)) if journal.is_stale() => self.recoveries.push((id, journal)),
+                    Ok(_) => {}
+                    Err(err) => error!("failed to read recovery journal of {}: {}", file, err),
+                }
+            }
+            let reload = self
+                .pending_reloads
+                .iter()
+                .position(|(file, _)| view.is_file(file));
+            if let Some(idx) = reload {
+                let (_, old_id) = self.pending_reloads.remove(idx);
+                self.replace_view(old_id, id, view);
+                continue;
+            }
+            for mark in self.file_marks.values_mut() {
+                if mark.view_id.is_none() && view.is_file(&mark.file) {
+                    mark.view_id = Some(id);
+                }
+            }
+            if let Some((file, line, column)) = self.pending_jump.take() {
+                if view.is_file(&file) {
+                    view.goto(line, column);
+                } else {
+                    self.pending_jump = Some((file, line, column));
+                }
+            }
+            self.views.insert(id, view);
+            self.show_view(id);
         }
 
         if self.pending_open_requests.is_empty() {
@@ -358,25 +1342,174 @@ impl Editor {
         }
     }
 
-    pub fn process_core_events(&mut self) {
+    /// Waits for xi-core to confirm the save requests that were sent,
+    /// returns false if a save failed since the last call.
+    pub fn process_save_requests(&mut self) -> bool {
+        let mut done = vec![];
+        let mut failed = vec![];
+        for (idx, (view_id, task)) in self.pending_save_requests.iter_mut().enumerate() {
+            match task.poll() {
+                Ok(Async::Ready(())) => self.sent_saves.push(*view_id),
+                Ok(Async::NotReady) => continue,
+                Err(err) => failed.push((*view_id, err.to_string())),
+            }
+            done.push(idx);
+        }
+        for idx in done.iter().rev() {
+            drop(self.pending_save_requests.remove(*idx));
+        }
+        for (view_id, err) in failed {
+            self.finish_save(view_id, Err(err));
+        }
+        !mem::replace(&mut self.save_failed, false)
+    }
+
+    /// Reports the save of `view_id` once xi-core confirmed or refused it.
+    fn finish_save(&mut self, view_id: ViewId, result: Result<(), String>) {
+        self.changed = true;
+        let view = match self.views.get_mut(&view_id) {
+            Some(view) => view,
+            None => return,
+        };
+        let file = view.file().unwrap_or_default().to_owned();
+        match result.and_then(|()| file_stats(&file).map_err(|err| err.to_string())) {
+            Ok((lines, bytes)) => {
+                info!("saved {}", file);
+                view.mark_saved();
+                view.update_disk_state();
+                if self.options.undofile {
+                    store_undo_history(view, &file);
+                }
+                view.set_message(format!(
+                    "\"{}\" written {} lines, {} bytes",
+                    file, lines, bytes
+                ));
+            }
+            Err(err) => {
+                error!("could not save {}: {}", file, err);
+                self.error = Some(format!("Could not write \"{}\": {}", file, err));
+                self.save_failed = true;
+            }
+        }
+    }
+
+    /// Dispatches the events sent by xi-core, returns false once it is gone.
+    pub fn process_core_events(&mut self) -> bool {
         loop {
             match self.events.poll() {
                 Ok(Async::Ready(Some(event))) => {
                     self.dispatch_core_event(event);
                 }
                 Ok(Async::Ready(None)) => {
-                    error!("Error core stdout shut down => panicking");
-                    panic!("Error core stdout shut down");
+                    error!("Error core stdout shut down");
+                    return false;
                 }
-                Ok(Async::NotReady) => break,
+                Ok(Async::NotReady) => return true,
                 Err(_) => {
-                    error!("Error while polling core => panicking");
-                    panic!("Error while polling core");
+                    error!("Error while polling core");
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// Whether a buffer has changes to write to its recovery journal, or a
+    /// journal to remove.
+    pub fn needs_journals(&self) -> bool {
+        self.views
+            .values()
+            .any(|view| view.is_modified() || view.journal().is_some())
+    }
+
+    /// Writes a recovery journal for every modified buffer whose changes
+    /// differ from the last one, and removes the journals of saved buffers.
+    pub fn write_journals(&mut self) {
+        for view in self.views.values_mut() {
+            let file = match view.file() {
+                Some(file) => file.to_owned(),
+                None => continue,
+            };
+            // The changes can't be written once the saved text dropped out
+            // of the undo history, the last journal is kept until the buffer
+            // is saved then.
+            let deltas = match view.unsaved_changes() {
+                Some(deltas) => deltas,
+                None if view.is_modified() => continue,
+                None => Vec::new(),
+            };
+            if deltas.is_empty() {
+                if view.journal().is_some() {
+                    if let Err(err) = swap::remove(&file) {
+                        error!("failed to remove recovery journal of {}: {}", file, err);
+                    }
+                    view.set_journal(None);
+                }
+                continue;
+            }
+            let disk_hash = match view.disk_hash() {
+                Some(disk_hash) => disk_hash,
+                None => continue,
+            };
+            let mut hasher = DefaultHasher::new();
+            deltas.hash(&mut hasher);
+            let hash = hasher.finish();
+            if view.journal() == Some(hash) {
+                continue;
+            }
+            match swap::write(&Journal::new(&file, disk_hash, deltas)) {
+                Ok(()) => view.set_journal(Some(hash)),
+                Err(err) => error!("failed to write recovery journal of {}: {}", file, err),
+            }
+        }
+    }
+
+    pub fn remove_journals(&mut self) {
+        for view in self.views.values_mut() {
+            if let (Some(file), Some(_)) = (view.file(), view.journal()) {
+                if let Err(err) = swap::remove(file) {
+                    error!("failed to remove recovery journal of {}: {}", file, err);
                 }
             }
+            view.set_journal(None);
+        }
+    }
+
+    pub fn take_recovery(&mut self) -> Option<(ViewId, Journal)> {
+        if self.recoveries.is_empty() {
+            None
+        } else {
+            Some(self.recoveries.remove(0))
+        }
+    }
+
+    /// Makes the changes of `journal` to the buffer of `view_id`, if the
+    /// file is still the one they were made to.
+    pub fn recover(&mut self, view_id: ViewId, journal: &Journal) {
+        if let Some(view) = self.views.get_mut(&view_id) {
+            if view.disk_hash() != Some(journal.hash) {
+                self.error = Some(format!(
+                    "\"{}\" changed since the recovery journal was written",
+                    journal.file
+                ));
+                return;
+            }
+            info!("recovering {}", journal.file);
+            view.recover(&journal.deltas);
+        }
+        self.delete_journal(journal);
+    }
+
+    pub fn delete_journal(&mut self, journal: &Journal) {
+        if let Err(err) = swap::remove(&journal.file) {
+            self.error = Some(format!("Could not delete recovery journal: {}", err));
         }
     }
 
+    pub fn cancel_saves(&mut self) {
+        self.pending_save_requests.clear();
+        self.sent_saves.clear();
+    }
+
     pub fn process_delayed_events(&mut self) {
         let delayed_events: Vec<CoreEvent> = self.delayed_events.drain(..).collect();
         for event in delayed_events {
@@ -384,15 +1517,159 @@ impl Editor {
         }
     }
 
-    pub fn render<W: Write>(&mut self, term: &mut W, state: &str) {
-        if let Some(view) = self.views.get_mut(&self.current_view) {
-            view.render(term, &self.styles, state);
+    pub fn render(&mut self, screen: &mut Screen, state: &str) {
+        self.render_tabline(screen);
+        self.render_separators(screen);
+        // The focused pane is rendered last so that it gets the cursor.
+        let current = self.current_pane;
+        let mut pane_ids: Vec<PaneId> = self
+            .rects
+            .iter()
+            .map(|(pane_id, _)| *pane_id)
+            .filter(|pane_id| *pane_id != current)
+            .collect();
+        pane_ids.push(current);
+        for pane_id in pane_ids {
+            let active = pane_id == current;
+            if let Some(pane) = self.panes.get_mut(&pane_id) {
+                if let Some(view) = self.views.get_mut(&pane.view_id) {
+                    let state = if active { state } else { "" };
+                    view.render(
+                        screen,
+                        &mut pane.window,
+                        &self.styles,
+                        &self.options,
+                        state,
+                        active,
+                    );
+                }
+            }
         }
     }
 
-    pub fn render_error<W: Write>(&mut self, term: &mut W, msg: &str) {
-        if let Some(view) = self.views.get_mut(&self.current_view) {
-            view.render_error(term, msg);
+    fn render_tabline(&self, screen: &mut Screen) {
+        let current_view = self.panes.get(&self.current_pane).map(|pane| pane.view_id);
+        let mut views: Vec<Option<ViewId>> = self.tabs.iter().map(Tab::current_view).collect();
+        views.insert(self.current_tab, current_view);
+
+        screen.clear_line(0, 0);
+        let mut width = 0;
+        for (idx, view_id) in views.into_iter().enumerate() {
+            let view = view_id.and_then(|view_id| self.views.get(&view_id));
+            let label = match view {
+                Some(view) if view.is_modified() => format!(" {} [+] ", view.name()),
+                Some(view) => format!(" {} ", view.name()),
+                None => " [No Name] ".to_owned(),
+            };
+            let len = label.chars().count() as u16;
+            if width + len > self.size.0 {
+                break;
+            }
+            let style = if idx == self.current_tab {
+                CellStyle::inverse()
+            } else {
+                CellStyle::default()
+            };
+            width += screen.print(width, 0, &label, style);
+        }
+    }
+
+    fn render_separators(&self, screen: &mut Screen) {
+        for (_, rect) in &self.rects {
+            if rect.x + rect.width >= self.size.0 {
+                continue;
+            }
+            for row in rect.y..rect.y + rect.height {
+                screen.print(rect.x + rect.width, row, "|", CellStyle::default());
+            }
+        }
+    }
+
+    pub fn render_quickfix(&mut self, screen: &mut Screen) {
+        self.quickfix.render(screen, self.size.1 + 1);
+    }
+
+    pub fn render_error(&mut self, screen: &mut Screen, msg: &str) {
+        if let Some(pane) = self.panes.get(&self.current_pane) {
+            if let Some(view) = self.views.get_mut(&pane.view_id) {
+                view.render_error(screen, &pane.window, msg);
+            }
         }
     }
 }
+
+fn file_stats(file: &str) -> Result<(usize, usize), io::Error> {
+    let content = fs::read(file)?;
+    let mut lines = content.iter().filter(|byte| **byte == b'\n').count();
+    if content.last().is_some_and(|byte| *byte != b'\n') {
+        lines += 1;
+    }
+    Ok((lines, content.len()))
+}
+
+fn store_undo_history(view: &View, file: &str) {
+    let history = match view.undo_history() {
+        Some(history) => history,
+        None => return,
+    };
+    if let Err(err) = undo::store(file, history) {
+        error!("failed to store undo history of {}: {}", file, err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::undo::UndoHistory;
+    use crate::vix::VixServiceBuilder;
+    use futures::future;
+    use serde_json::json;
+    use std::env;
+    use std::process;
+    use tokio::runtime::current_thread::Runtime;
+
+    fn update(ops: Value) -> Update {
+        Update {
+            rev: None,
+            operations: serde_json::from_value(ops).unwrap(),
+            pristine: false,
+            view_id: ViewId(1),
+        }
+    }
+
+    #[test]
+    fn the_journal_is_kept_when_the_undo_history_is_reset() {
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(future::lazy(|| {
+                let mut core = process::Command::new("sh");
+                core.args(["-c", "cat >/dev/null"]);
+                let (builder, events) = VixServiceBuilder::new();
+                let (client, _) = xrl::spawn_command(core, builder).unwrap();
+                let mut editor = Editor::new(client.clone(), events);
+                let file = env::temp_dir().join(format!("vix-journal-{}.txt", process::id()));
+                let file = file.to_string_lossy().into_owned();
+                let mut view = View::new(ViewClient::new(client, ViewId(1)), Some(file.clone()));
+                view.update_cache(update(
+                    json!([{ "op": "ins", "n": 1, "lines": [{ "text": "a" }] }]),
+                ));
+                view.update_cache(update(json!([
+                    { "op": "skip", "n": 1 },
+                    { "op": "ins", "n": 1, "lines": [{ "text": "b" }] },
+                ])));
+                editor.views.insert(ViewId(1), view);
+
+                editor.write_journals();
+                assert!(swap::read(&file).unwrap().is_some());
+                let view = editor.views.get_mut(&ViewId(1)).unwrap();
+                view.set_undo_history(UndoHistory::new(swap::now()));
+                editor.write_journals();
+                assert!(swap::read(&file).unwrap().is_some());
+
+                editor.remove_journals();
+                assert!(swap::read(&file).unwrap().is_none());
+                Ok::<(), ()>(())
+            }))
+            .unwrap();
+    }
+}
@@ -0,0 +1,414 @@
+use crate::window::Window;
+use std::cmp;
+use std::collections::HashMap;
+use xrl::ViewId;
+
+pub type PaneId = usize;
+
+/// `Horizontal` stacks panes on top of each other (`:split`), `Vertical`
+/// puts them side by side (`:vsplit`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitDirection {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Left,
+    Down,
+    Up,
+    Right,
+}
+
+/// A screen area, with `x` and `y` starting at 0.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+pub struct Pane {
+    pub view_id: ViewId,
+    pub window: Window,
+}
+
+impl Pane {
+    pub fn new(view_id: ViewId, window: Window) -> Self {
+        Pane { view_id, window }
+    }
+}
+
+pub struct Tab {
+    pub layout: Layout,
+    pub panes: HashMap<PaneId, Pane>,
+    pub current_pane: PaneId,
+}
+
+impl Tab {
+    pub fn current_view(&self) -> Option<ViewId> {
+        self.panes.get(&self.current_pane).map(|pane| pane.view_id)
+    }
+}
+
+/// The tree of splits. The sizes of a split are the rows (horizontal) or
+/// columns (vertical) of its children, and are fitted to the available
+/// space whenever the layout is computed.
+#[derive(Debug, Clone)]
+pub enum Layout {
+    Pane(PaneId),
+    Split {
+        direction: SplitDirection,
+        children: Vec<Layout>,
+        sizes: Vec<u16>,
+    },
+}
+
+impl Layout {
+    pub fn compute(&mut self, rect: Rect, rects: &mut Vec<(PaneId, Rect)>) {
+        match self {
+            Layout::Pane(pane_id) => rects.push((*pane_id, rect)),
+            Layout::Split {
+                direction,
+                children,
+                sizes,
+            } => {
+                // Vertical splits are separated by a column, horizontal splits
+                // by the status line that is part of each pane.
+                let separator = match direction {
+                    SplitDirection::Horizontal => 0,
+                    SplitDirection::Vertical => 1,
+                };
+                let available = match direction {
+                    SplitDirection::Horizontal => rect.height,
+                    SplitDirection::Vertical => rect
+                        .width
+                        .saturating_sub(separator * (children.len() as u16 - 1)),
+                };
+                fit(sizes, available);
+
+                let mut offset = 0;
+                for (child, size) in children.iter_mut().zip(sizes.iter()) {
+                    let child_rect = match direction {
+                        SplitDirection::Horizontal => Rect {
+                            y: rect.y + offset,
+                            height: *size,
+                            ..rect
+                        },
+                        SplitDirection::Vertical => Rect {
+                            x: rect.x + offset,
+                            width: *size,
+                            ..rect
+                        },
+                    };
+                    child.compute(child_rect, rects);
+                    offset += size + separator;
+                }
+            }
+        }
+    }
+
+    /// Splits `target` in two, putting `pane` above or left of it.
+    pub fn split(&mut self, target: PaneId, pane: PaneId, direction: SplitDirection) -> bool {
+        match self {
+            Layout::Pane(pane_id) if *pane_id != target => false,
+            Layout::Pane(_) => {
+                *self = Layout::Split {
+                    direction,
+                    children: vec![Layout::Pane(pane), Layout::Pane(target)],
+                    sizes: vec![0, 0],
+                };
+                true
+            }
+            Layout::Split {
+                direction: split_direction,
+                children,
+                sizes,
+            } => {
+                if *split_direction == direction {
+                    if let Some(idx) = children.iter().position(|child| child.is_pane(target)) {
+                        let half = sizes[idx] / 2;
+                        sizes[idx] -= half;
+                        sizes.insert(idx, half);
+                        children.insert(idx, Layout::Pane(pane));
+                        return true;
+                    }
+                }
+                children
+                    .iter_mut()
+                    .any(|child| child.split(target, pane, direction))
+            }
+        }
+    }
+
+    /// Removes `target`, giving its space to the next or previous sibling.
+    pub fn remove(&mut self, target: PaneId) -> bool {
+        let children = match self {
+            Layout::Pane(_) => return false,
+            Layout::Split {
+                children, sizes, ..
+            } => match children.iter().position(|child| child.is_pane(target)) {
+                Some(idx) => {
+                    children.remove(idx);
+                    let freed = sizes.remove(idx);
+                    let neighbor = cmp::min(idx, sizes.len() - 1);
+                    sizes[neighbor] += freed;
+                    children
+                }
+                None => {
+                    return children.iter_mut().any(|child| child.remove(target));
+                }
+            },
+        };
+        if children.len() == 1 {
+            let child = children.remove(0);
+            *self = child;
+        }
+        true
+    }
+
+    pub fn contains(&self, target: PaneId) -> bool {
+        match self {
+            Layout::Pane(pane_id) => *pane_id == target,
+            Layout::Split { children, .. } => children.iter().any(|child| child.contains(target)),
+        }
+    }
+
+    fn is_pane(&self, target: PaneId) -> bool {
+        match self {
+            Layout::Pane(pane_id) => *pane_id == target,
+            Layout::Split { .. } => false,
+        }
+    }
+
+    pub fn panes(&self) -> Vec<PaneId> {
+        match self {
+            Layout::Pane(pane_id) => vec![*pane_id],
+            Layout::Split { children, .. } => {
+                children.iter().flat_map(|child| child.panes()).collect()
+            }
+        }
+    }
+
+    pub fn equalize(&mut self) {
+        if let Layout::Split {
+            children, sizes, ..
+        } = self
+        {
+            for size in sizes.iter_mut() {
+                *size = 0;
+            }
+            for child in children.iter_mut() {
+                child.equalize();
+            }
+        }
+    }
+
+    /// Grows (or shrinks if `delta` is negative) `target` in `direction`,
+    /// taking the space from its next or previous sibling.
+    pub fn resize(&mut self, target: PaneId, direction: SplitDirection, delta: i32) -> bool {
+        if let Layout::Split {
+            direction: split_direction,
+            children,
+            sizes,
+        } = self
+        {
+            let idx = match children.iter().position(|child| child.contains(target)) {
+                Some(idx) => idx,
+                None => return false,
+            };
+            if children[idx].resize(target, direction, delta) {
+                return true;
+            }
+            if *split_direction != direction || children.len() < 2 {
+                return false;
+            }
+            let other = if idx + 1 < children.len() {
+                idx + 1
+            } else {
+                idx - 1
+            };
+            let min = match direction {
+                SplitDirection::Horizontal => 2,
+                SplitDirection::Vertical => 1,
+            };
+            // Panes already below the minimum, e.g. on a small terminal,
+            // neither give nor lose space.
+            let delta = if delta > 0 {
+                cmp::min(delta, cmp::max(0, i32::from(sizes[other]) - min))
+            } else {
+                -cmp::min(-delta, cmp::max(0, i32::from(sizes[idx]) - min))
+            };
+            if delta == 0 {
+                return true;
+            }
+            sizes[idx] = (i32::from(sizes[idx]) + delta) as u16;
+            sizes[other] = (i32::from(sizes[other]) - delta) as u16;
+            return true;
+        }
+        false
+    }
+}
+
+/// Scales `sizes` to add up to `available`, splitting it evenly if there are
+/// no sizes yet.
+fn fit(sizes: &mut [u16], available: u16) {
+    let total: u32 = sizes.iter().map(|size| u32::from(*size)).sum();
+    if total == u32::from(available) || sizes.is_empty() {
+        return;
+    }
+    let count = sizes.len() as u32;
+    for size in sizes.iter_mut() {
+        *size = (u32::from(*size) * u32::from(available))
+            .checked_div(total)
+            .unwrap_or(u32::from(available) / count) as u16;
+    }
+    let fitted: u16 = sizes.iter().sum();
+    if let Some(last) = sizes.last_mut() {
+        *last += available.saturating_sub(fitted);
+    }
+}
+
+/// Finds the pane next to `from` in `direction`, preferring the closest and
+/// then the top or left most one.
+pub fn neighbor(rects: &[(PaneId, Rect)], from: PaneId, direction: Direction) -> Option<PaneId> {
+    let rect = rects.iter().find(|(pane_id, _)| *pane_id == from)?.1;
+    let overlaps = |a: u16, a_len: u16, b: u16, b_len: u16| a < b + b_len && b < a + a_len;
+    rects
+        .iter()
+        .filter(|(pane_id, other)| {
+            *pane_id != from
+                && match direction {
+                    Direction::Left => {
+                        other.x + other.width <= rect.x
+                            && overlaps(other.y, other.height, rect.y, rect.height)
+                    }
+                    Direction::Right => {
+                        other.x >= rect.x + rect.width
+                            && overlaps(other.y, other.height, rect.y, rect.height)
+                    }
+                    Direction::Up => {
+                        other.y + other.height <= rect.y
+                            && overlaps(other.x, other.width, rect.x, rect.width)
+                    }
+                    Direction::Down => {
+                        other.y >= rect.y + rect.height
+                            && overlaps(other.x, other.width, rect.x, rect.width)
+                    }
+                }
+        })
+        .min_by_key(|(_, other)| {
+            let distance = match direction {
+                Direction::Left => rect.x - (other.x + other.width),
+                Direction::Right => other.x - (rect.x + rect.width),
+                Direction::Up => rect.y - (other.y + other.height),
+                Direction::Down => other.y - (rect.y + rect.height),
+            };
+            (distance, other.y, other.x)
+        })
+        .map(|(pane_id, _)| *pane_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compute(layout: &mut Layout, width: u16, height: u16) -> Vec<(PaneId, Rect)> {
+        let mut rects = Vec::new();
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        layout.compute(rect, &mut rects);
+        rects
+    }
+
+    fn heights(layout: &mut Layout) -> Vec<u16> {
+        compute(layout, 80, 20)
+            .iter()
+            .map(|(_, rect)| rect.height)
+            .collect()
+    }
+
+    #[test]
+    fn splits_fill_the_screen() {
+        let mut layout = Layout::Pane(0);
+        assert!(layout.split(0, 1, SplitDirection::Vertical));
+        assert!(layout.split(0, 2, SplitDirection::Horizontal));
+        assert!(!layout.split(7, 3, SplitDirection::Horizontal));
+        let rects = compute(&mut layout, 81, 20);
+        let rect = |id| rects.iter().find(|(pane, _)| *pane == id).unwrap().1;
+        assert_eq!(rect(1).width + 1 + rect(0).width, 81);
+        assert_eq!(rect(2).height + rect(0).height, 20);
+        assert_eq!(rect(2).x, rect(0).x);
+        assert_eq!(layout.panes(), vec![1, 2, 0]);
+
+        assert_eq!(neighbor(&rects, 1, Direction::Right), Some(2));
+        assert_eq!(neighbor(&rects, 0, Direction::Up), Some(2));
+        assert_eq!(neighbor(&rects, 0, Direction::Down), None);
+    }
+
+    #[test]
+    fn removing_a_pane_gives_its_space_to_a_sibling() {
+        let mut layout = Layout::Pane(0);
+        layout.split(0, 1, SplitDirection::Horizontal);
+        layout.split(0, 2, SplitDirection::Horizontal);
+        heights(&mut layout);
+        assert!(layout.remove(2));
+        assert_eq!(heights(&mut layout).iter().sum::<u16>(), 20);
+        assert!(layout.remove(1));
+        assert!(layout.is_pane(0));
+    }
+
+    #[test]
+    fn resize_takes_space_from_a_sibling() {
+        let mut layout = Layout::Pane(0);
+        layout.split(0, 1, SplitDirection::Horizontal);
+        assert_eq!(heights(&mut layout), vec![10, 10]);
+        assert!(layout.resize(1, SplitDirection::Horizontal, 3));
+        assert_eq!(heights(&mut layout), vec![13, 7]);
+        assert!(layout.resize(0, SplitDirection::Horizontal, -100));
+        assert_eq!(heights(&mut layout), vec![18, 2]);
+        assert!(!layout.resize(0, SplitDirection::Vertical, 1));
+    }
+
+    #[test]
+    fn resize_next_to_a_pane_at_its_minimum() {
+        let mut layout = Layout::Pane(0);
+        layout.split(0, 1, SplitDirection::Horizontal);
+        heights(&mut layout);
+        layout.resize(1, SplitDirection::Horizontal, 100);
+        assert_eq!(heights(&mut layout), vec![18, 2]);
+        assert!(layout.resize(1, SplitDirection::Horizontal, 5));
+        assert_eq!(heights(&mut layout), vec![18, 2]);
+
+        // Below the minimum, e.g. after the terminal shrank.
+        let mut layout = Layout::Split {
+            direction: SplitDirection::Horizontal,
+            children: vec![Layout::Pane(0), Layout::Pane(1)],
+            sizes: vec![5, 1],
+        };
+        let sizes = |layout: &mut Layout| -> Vec<u16> {
+            compute(layout, 80, 6)
+                .iter()
+                .map(|(_, rect)| rect.height)
+                .collect()
+        };
+        assert!(layout.resize(0, SplitDirection::Horizontal, 1));
+        assert_eq!(sizes(&mut layout), vec![5, 1]);
+        assert!(layout.resize(1, SplitDirection::Horizontal, -1));
+        assert_eq!(sizes(&mut layout), vec![5, 1]);
+        assert!(layout.resize(1, SplitDirection::Horizontal, 2));
+        assert_eq!(sizes(&mut layout), vec![3, 3]);
+    }
+}
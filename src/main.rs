@@ -1,26 +1,41 @@
+mod backend;
 mod client;
 mod command_prompt;
 mod editor;
-mod style;
+mod layout;
+mod marks;
+mod options;
+mod quickfix;
+mod screen;
+mod search;
+mod swap;
 mod tty;
+mod undo;
 mod view;
 mod vix;
 mod window;
+mod wrap;
 
 use futures::{Future, Stream};
 use slog::{Drain, Level, LevelFilter};
 use slog_scope::GlobalLoggerGuard;
+use std::env;
 use std::fs::OpenOptions;
-use xrl;
+use std::path::PathBuf;
+use std::process;
+use std::sync::{Arc, Mutex};
 
 #[macro_use]
 extern crate slog;
 #[macro_use]
 extern crate slog_scope;
 
+const LOG_FILE: &str = "vix.log";
+
 const HELP: &str = r#"
 ---
 Usage: vix <FILE> <FILE> ...
+       vix -r    list files with recovery journals
 
 Key bindings:
   'Esc' always returns to vix mode
@@ -28,20 +43,57 @@ Key bindings:
     'i' insert mode
     ':' command mode
     '/' search mode
+    '?' backward search mode
     'v' visual mode
 	'u' undo
-    'r' redo
+    'Ctrl-r' redo
+    'r{c}' replace character
+    'g-' 'g+' older / newer text state
+    'gg' 'G' first / last line
+    'gj' 'gk' down / up one screen row of a wrapped line
+    'zh' 'zl' 'zs' 'ze' scroll left / right / to the cursor, with nowrap
+    'zt' 'zz' 'zb' scroll the cursor line to the top / middle / bottom
+    'Ctrl-e' 'Ctrl-y' scroll a line down / up
+    'Ctrl-d' 'Ctrl-u' 'Ctrl-f' 'Ctrl-b' scroll half a page / a page
+    'm{a-z}' 'm{A-Z}' set buffer / file mark
+    '`{mark}' ''{mark}' jump to mark / its line, '``' '''' back
+    'Ctrl-o' 'Ctrl-i' older / newer position in the jump list
+    'Ctrl-z' suspend vix, 'fg' in the shell resumes it
+    'g;' 'g,' older / newer position in the change list, '`.' last change
     'dd+' delete line(s)
     'p' paste
     'n' next
     'N' prev
+    '*' '#' next / prev word under cursor
+    'g*' 'g#' next / prev partial word under cursor
+    'gd' 'gD' goto local / global declaration
+    'gt' 'gT' next / prev tab page
+    'Ctrl-w s/v' split window, 'Ctrl-w h/j/k/l/w' move between windows
+    'Ctrl-w c/o' close window / all other windows
+    'Ctrl-w =/+/-/</>' equalize / resize windows
   command mode:
     '#' goto line
     'w' write
-    'q' quit
+    'q' quit, or close the window if there are several
+    'q!' 'qa' 'qa!' quit discarding changes / quit all / quit all discarding
+    'wa' 'wqa' 'xa' write all / write all and quit
+    'x' write if modified and quit
+    'sp [FILE]' 'vs [FILE]' split window horizontally / vertically
+    'close' 'only' close window / all other windows
+    'tabnew [FILE]' 'tabe FILE' open a tab page
+    'tabn' 'tabp' 'tabclose' 'tabonly' switch / close tab pages
     'wq' write and quit
+    'set OPTION' ignorecase, smartcase, regex, wrapscan, grepprg, autoread,
+                 undofile, number, relativenumber, wrap, linebreak,
+                 showbreak, scrolloff, sidescroll, sidescrolloff
+    'checktime' check for files changed outside of vix
+    'earlier N' 'later N' undo / redo N states, or time with s, m, h, d
+    'vimgrep /PATTERN/[g][j] FILES' search files into the quickfix list
+    'grep ARGS' run grepprg into the quickfix list
+    'copen' 'cn' 'cp' 'cc N' browse the quickfix list
   search mode:
-    'TERM' work in progress ...
+    'TERM' search, '\c' ignore case, '\C' match case, '\v' regex
+    'TERM/OFFSET' e.g. '/foo/e+1', '/foo/+2'
   visual mode:
     'i' insert mode
     'p' paste
@@ -53,12 +105,18 @@ Key bindings:
 fn setup_log(file: Option<String>) -> GlobalLoggerGuard {
     let drain = match file {
         Some(file) => {
-            let file = OpenOptions::new()
+            let file = match OpenOptions::new()
                 .create(true)
                 .write(true)
                 .truncate(true)
-                .open(file)
-                .unwrap();
+                .open(&file)
+            {
+                Ok(file) => file,
+                Err(err) => {
+                    eprintln!("Failed to open the log file {}: {}", file, err);
+                    process::exit(1);
+                }
+            };
 
             let decorator = slog_term::PlainDecorator::new(file);
             let drain = slog_term::CompactFormat::new(decorator).build().fuse();
@@ -74,10 +132,26 @@ fn setup_log(file: Option<String>) -> GlobalLoggerGuard {
     slog_scope::set_global_logger(log)
 }
 
+fn list_journals() {
+    match swap::list() {
+        Ok(ref journals) if journals.is_empty() => println!("No recovery journals found."),
+        Ok(journals) => {
+            println!("Recovery journals, open the file to recover it:");
+            for journal in journals {
+                println!("  {}", journal.describe());
+            }
+        }
+        Err(err) => {
+            eprintln!("Failed to list recovery journals: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
 fn main() {
-    let _guard = setup_log(Some("vix.log".to_owned()));
+    let _guard = setup_log(Some(LOG_FILE.to_owned()));
     let mut args = Vec::new();
-    args.extend(std::env::args().skip(1));
+    args.extend(env::args().skip(1));
 
     let header = format!(
         "{} {} (c) {} \n\n{}",
@@ -99,9 +173,24 @@ fn main() {
         }
     }
 
+    if args.first().is_some_and(|arg| arg == "-r") {
+        args.remove(0);
+        if args.is_empty() {
+            list_journals();
+            return;
+        }
+    }
+
     info!("Starting xi-core");
     let (vix_builder, core_events_rx) = vix::VixServiceBuilder::new();
-    let (client, core_stderr) = xrl::spawn("xi-core", vix_builder);
+    let (client, core_stderr) = match xrl::spawn("xi-core", vix_builder) {
+        Ok(spawned) => spawned,
+        Err(err) => {
+            error!("Error starting xi-core: {}", err);
+            eprintln!("Error starting xi-core: {}", err);
+            process::exit(1);
+        }
+    };
 
     let err_log = core_stderr
         .for_each(|msg| {
@@ -114,11 +203,16 @@ fn main() {
     });
 
     info!("Starting vix");
+    let log_file = env::current_dir()
+        .map(|dir| dir.join(LOG_FILE))
+        .unwrap_or_else(|_| PathBuf::from(LOG_FILE));
+    let terminal = tty::TerminalGuard::new(log_file);
     let mut vix = match vix::Vix::new(client, core_events_rx) {
         Ok(vix) => vix,
         Err(err) => {
             error!("Error starting vix: {}", err);
-            ::std::process::exit(1);
+            terminal.fatal(&format!("Error starting vix: {}", err));
+            process::exit(1);
         }
     };
     vix.handle_cmd(command_prompt::Command::SetTheme(
@@ -128,7 +222,17 @@ fn main() {
         vix.handle_cmd(command_prompt::Command::Open(Some(file)));
     }
 
-    tokio::run(vix.map_err(|err| {
+    let failed = Arc::new(Mutex::new(None));
+    let reported = failed.clone();
+    tokio::run(vix.map_err(move |err| {
         error!("{}", err);
+        if let Ok(mut reported) = reported.lock() {
+            *reported = Some(err.to_string());
+        }
     }));
+    // The terminal is usable again once vix is dropped.
+    if let Some(err) = failed.lock().ok().and_then(|mut failed| failed.take()) {
+        terminal.fatal(&err);
+        process::exit(1);
+    }
 }
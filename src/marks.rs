@@ -0,0 +1,290 @@
+use crate::swap::{self, SwapError};
+use crate::view::Cursor;
+use serde_derive::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use xrl::{Line, LineCache, Operation, OperationType, ViewId};
+
+const JUMP_LIST_SIZE: usize = 100;
+const CHANGE_LIST_SIZE: usize = 100;
+
+/// Maps a line of the document before an update to the line it moved to.
+///
+/// A skip directly followed by inserted or invalidated lines is treated as
+/// lines changed in place, deleted lines map to the line after them.
+pub fn map_line(operations: &[Operation], line: u64) -> u64 {
+    let (mut old, mut new) = (0, 0);
+    let mut ops = operations.iter().peekable();
+    while let Some(op) = ops.next() {
+        let count = op.nb_lines;
+        match op.operation_type {
+            OperationType::Copy | OperationType::Update => {
+                if line < old + count {
+                    return new + line - old;
+                }
+                old += count;
+                new += count;
+            }
+            OperationType::Skip => {
+                let replaced = match ops.peek() {
+                    Some(next) => match next.operation_type {
+                        OperationType::Insert | OperationType::Invalidate => next.nb_lines,
+                        _ => 0,
+                    },
+                    None => 0,
+                };
+                if line < old + count {
+                    return new + cmp::min(line - old, replaced);
+                }
+                old += count;
+            }
+            OperationType::Insert | OperationType::Invalidate => new += count,
+        }
+    }
+    new + line.saturating_sub(old)
+}
+
+/// Lines removed and added by a run of operations between copied lines.
+#[derive(Default)]
+struct Hunk<'a> {
+    /// The first added line in the new document.
+    start: u64,
+    /// The removed lines, `None` if they were not cached.
+    removed: Vec<Option<&'a str>>,
+    /// The added lines, `None` if they are invalid.
+    added: Vec<Option<&'a Line>>,
+}
+
+impl<'a> Hunk<'a> {
+    /// Returns the position of the first line whose text changed. Lines
+    /// scrolled in or out of the cache keep the line count, lines resent
+    /// for a new cursor position keep the text.
+    fn change(&self) -> Option<Cursor> {
+        let differs = |(removed, added): (&Option<&str>, &Option<&Line>)| match (removed, added) {
+            // The cache keeps lines without their line break.
+            (Some(removed), Some(added)) => {
+                *removed != added.text.strip_suffix('\n').unwrap_or(&added.text)
+            }
+            _ => false,
+        };
+        let idx = match self.removed.iter().zip(&self.added).position(differs) {
+            Some(idx) => idx,
+            None if self.removed.len() != self.added.len() => {
+                cmp::min(self.removed.len(), self.added.len())
+            }
+            None => return None,
+        };
+        let column = match self.added.get(idx) {
+            Some(Some(line)) => line.cursor.first().cloned().unwrap_or(0),
+            _ => 0,
+        };
+        Some(Cursor {
+            line: self.start + idx as u64,
+            column,
+        })
+    }
+}
+
+/// Returns the position of the first change to the text made by an update,
+/// given the cache it applies to.
+pub fn find_change(operations: &[Operation], cache: &LineCache) -> Option<Cursor> {
+    // The first update fills an empty cache with the opened file.
+    if cache.before() + cache.lines().len() as u64 + cache.after() == 0 {
+        return None;
+    }
+    let cached = |line: u64| {
+        line.checked_sub(cache.before())
+            .and_then(|idx| cache.lines().get(idx as usize))
+            .map(|line| line.text.as_str())
+    };
+    let (mut old, mut new) = (0, 0);
+    let mut hunk = Hunk::default();
+    for op in operations {
+        let count = op.nb_lines;
+        match op.operation_type {
+            OperationType::Copy | OperationType::Update => {
+                if let Some(change) = hunk.change() {
+                    return Some(change);
+                }
+                old += count;
+                new += count;
+                hunk = Hunk {
+                    start: new,
+                    ..Hunk::default()
+                };
+            }
+            OperationType::Skip => {
+                hunk.removed.extend((old..old + count).map(cached));
+                old += count;
+            }
+            OperationType::Insert => {
+                hunk.added.extend(op.lines.iter().map(Some));
+                new += count;
+            }
+            OperationType::Invalidate => {
+                hunk.added.extend((0..count).map(|_| None));
+                new += count;
+            }
+        }
+    }
+    hunk.change()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileMark {
+    pub file: String,
+    pub line: u64,
+    pub column: u64,
+    /// The view showing the file, if it is open.
+    #[serde(skip)]
+    pub view_id: Option<ViewId>,
+}
+
+fn file_marks_path() -> Result<PathBuf, SwapError> {
+    Ok(swap::state_dir("marks")?.join("file_marks.json"))
+}
+
+pub fn load_file_marks() -> Result<HashMap<char, FileMark>, SwapError> {
+    match fs::read(file_marks_path()?) {
+        Ok(content) => Ok(serde_json::from_slice(&content)?),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn store_file_marks(marks: &HashMap<char, FileMark>) -> Result<(), SwapError> {
+    fs::create_dir_all(swap::state_dir("marks")?)?;
+    fs::write(file_marks_path()?, serde_json::to_vec(marks)?)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct Jump {
+    pub view_id: ViewId,
+    pub cursor: Cursor,
+}
+
+/// The positions visited with `Ctrl-o` and `Ctrl-i`, oldest first.
+#[derive(Debug, Default)]
+pub struct JumpList {
+    jumps: Vec<Jump>,
+    /// The entry `Ctrl-o` goes to is the one before, `jumps.len()` when not
+    /// navigating the list.
+    current: usize,
+}
+
+impl JumpList {
+    /// Records the position a jump starts from, replacing an older entry
+    /// for the same line.
+    pub fn push(&mut self, jump: Jump) {
+        self.jumps
+            .retain(|other| other.view_id != jump.view_id || other.cursor.line != jump.cursor.line);
+        self.jumps.push(jump);
+        if self.jumps.len() > JUMP_LIST_SIZE {
+            self.jumps.remove(0);
+        }
+        self.current = self.jumps.len();
+    }
+
+    /// Returns the previous position, `from` being the current one.
+    pub fn back(&mut self, from: Jump) -> Option<Jump> {
+        if self.current == 0 {
+            return None;
+        }
+        if self.current == self.jumps.len() {
+            // Remember where we came from for Ctrl-i.
+            self.push(from);
+            self.current = self.jumps.len() - 1;
+        }
+        self.current -= 1;
+        self.jumps.get(self.current).cloned()
+    }
+
+    pub fn forward(&mut self) -> Option<Jump> {
+        if self.current + 1 >= self.jumps.len() {
+            return None;
+        }
+        self.current += 1;
+        self.jumps.get(self.current).cloned()
+    }
+
+    /// The position before the latest jump, the `'` mark.
+    pub fn last(&self) -> Option<&Jump> {
+        self.jumps.last()
+    }
+
+    /// Moves the positions in `view_id` with the lines changed by an update.
+    pub fn update(&mut self, view_id: ViewId, operations: &[Operation]) {
+        for jump in self.jumps.iter_mut().filter(|jump| jump.view_id == view_id) {
+            jump.cursor.line = map_line(operations, jump.cursor.line);
+        }
+    }
+
+    pub fn replace_view(&mut self, old_id: ViewId, view_id: ViewId) {
+        for jump in self.jumps.iter_mut().filter(|jump| jump.view_id == old_id) {
+            jump.view_id = view_id;
+        }
+    }
+}
+
+/// The positions of the changes to a buffer, oldest first, for `g;` and
+/// `g,`.
+#[derive(Debug, Default)]
+pub struct ChangeList {
+    changes: Vec<Cursor>,
+    /// The entry `g;` goes to is the one before, `changes.len()` when not
+    /// navigating the list.
+    current: usize,
+}
+
+impl ChangeList {
+    /// Records a change, replacing the latest one if it is on the same line.
+    pub fn push(&mut self, change: Cursor) {
+        if self
+            .changes
+            .last()
+            .is_some_and(|last| last.line == change.line)
+        {
+            self.changes.pop();
+        }
+        self.changes.push(change);
+        if self.changes.len() > CHANGE_LIST_SIZE {
+            self.changes.remove(0);
+        }
+        self.current = self.changes.len();
+    }
+
+    /// The position of the latest change, the `.` mark.
+    pub fn last(&self) -> Option<&Cursor> {
+        self.changes.last()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    pub fn older(&mut self) -> Option<&Cursor> {
+        if self.current == 0 {
+            return None;
+        }
+        self.current -= 1;
+        self.changes.get(self.current)
+    }
+
+    pub fn newer(&mut self) -> Option<&Cursor> {
+        if self.current + 1 >= self.changes.len() {
+            return None;
+        }
+        self.current += 1;
+        self.changes.get(self.current)
+    }
+
+    pub fn update(&mut self, operations: &[Operation]) {
+        for change in &mut self.changes {
+            change.line = map_line(operations, change.line);
+        }
+    }
+}
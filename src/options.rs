@@ -0,0 +1,153 @@
+use crate::wrap::Wrap;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum OptionError {
+    Unknown(String),
+    InvalidArgument(String),
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionError::Unknown(name) => write!(f, "Unknown option: {}", name),
+            OptionError::InvalidArgument(arg) => write!(f, "Invalid argument: {}", arg),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub ignorecase: bool,
+    pub smartcase: bool,
+    pub regex: bool,
+    pub wrapscan: bool,
+    pub grepprg: String,
+    /// Reload files changed outside of vix when the buffer is unmodified.
+    pub autoread: bool,
+    /// Keep the undo history of saved files across sessions.
+    pub undofile: bool,
+    pub number: bool,
+    pub relativenumber: bool,
+    /// Break lines longer than the window into several rows.
+    pub wrap: bool,
+    pub linebreak: bool,
+    pub showbreak: String,
+    /// Minimal number of lines to keep above and below the cursor.
+    pub scrolloff: u16,
+    /// Minimal number of columns to scroll horizontally, 0 to center the
+    /// cursor.
+    pub sidescroll: u16,
+    /// Minimal number of columns to keep left and right of the cursor.
+    pub sidescrolloff: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineNumbers {
+    Off,
+    Absolute,
+    /// Distances to the cursor line, which shows 0.
+    Relative,
+    /// Distances to the cursor line, which shows its number.
+    Hybrid,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            ignorecase: false,
+            smartcase: false,
+            regex: false,
+            wrapscan: true,
+            grepprg: "grep -rn".to_owned(),
+            autoread: true,
+            undofile: false,
+            number: true,
+            relativenumber: false,
+            wrap: true,
+            linebreak: false,
+            showbreak: String::new(),
+            scrolloff: 5,
+            sidescroll: 0,
+            sidescrolloff: 0,
+        }
+    }
+}
+
+impl Options {
+    pub fn line_numbers(&self) -> LineNumbers {
+        match (self.number, self.relativenumber) {
+            (false, false) => LineNumbers::Off,
+            (true, false) => LineNumbers::Absolute,
+            (false, true) => LineNumbers::Relative,
+            (true, true) => LineNumbers::Hybrid,
+        }
+    }
+
+    pub fn wrap(&self) -> Option<Wrap> {
+        if !self.wrap {
+            return None;
+        }
+        Some(Wrap {
+            linebreak: self.linebreak,
+            showbreak: self.showbreak.clone(),
+        })
+    }
+
+    /// Applies a single `:set` argument, i.e. `name`, `noname`, `invname`,
+    /// `name!` or `name=value`.
+    pub fn set(&mut self, setting: &str) -> Result<(), OptionError> {
+        if let Some(idx) = setting.find('=') {
+            return self.set_value(&setting[..idx], &setting[idx + 1..]);
+        }
+        if let Some(name) = setting.strip_suffix('!') {
+            let flag = self.flag(name)?;
+            *flag = !*flag;
+        } else if setting.starts_with("inv") && self.flag(setting).is_err() {
+            let flag = self.flag(&setting[3..])?;
+            *flag = !*flag;
+        } else if setting.starts_with("no") && self.flag(setting).is_err() {
+            *self.flag(&setting[2..])? = false;
+        } else {
+            *self.flag(setting)? = true;
+        }
+        Ok(())
+    }
+
+    fn flag(&mut self, name: &str) -> Result<&mut bool, OptionError> {
+        match name {
+            "ignorecase" | "ic" => Ok(&mut self.ignorecase),
+            "smartcase" | "scs" => Ok(&mut self.smartcase),
+            "regex" | "re" => Ok(&mut self.regex),
+            "wrapscan" | "ws" => Ok(&mut self.wrapscan),
+            "autoread" | "ar" => Ok(&mut self.autoread),
+            "undofile" | "udf" => Ok(&mut self.undofile),
+            "number" | "nu" => Ok(&mut self.number),
+            "relativenumber" | "rnu" => Ok(&mut self.relativenumber),
+            "wrap" => Ok(&mut self.wrap),
+            "linebreak" | "lbr" => Ok(&mut self.linebreak),
+            _ => Err(OptionError::Unknown(name.into())),
+        }
+    }
+
+    fn set_value(&mut self, name: &str, value: &str) -> Result<(), OptionError> {
+        match name {
+            "grepprg" | "gp" => self.grepprg = value.to_owned(),
+            "showbreak" | "sbr" => self.showbreak = value.to_owned(),
+            "scrolloff" | "so" => self.scrolloff = parse_number(name, value)?,
+            "sidescroll" | "ss" => self.sidescroll = parse_number(name, value)?,
+            "sidescrolloff" | "siso" => self.sidescrolloff = parse_number(name, value)?,
+            _ => {
+                self.flag(name)?;
+                return Err(OptionError::InvalidArgument(format!("{}={}", name, value)));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<u16, OptionError> {
+    value
+        .parse()
+        .map_err(|_| OptionError::InvalidArgument(format!("{}={}", name, value)))
+}
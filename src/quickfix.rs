@@ -0,0 +1,384 @@
+use crate::screen::{CellStyle, Screen};
+use futures::sync::oneshot;
+use glob::glob;
+use regex::Regex;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::process;
+use std::thread;
+
+#[derive(Debug)]
+pub enum QuickfixError {
+    Pattern(String),
+    Glob(String),
+    Io(io::Error),
+    NoMatch(String),
+    NoErrors,
+    NoMoreItems,
+}
+
+impl fmt::Display for QuickfixError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QuickfixError::Pattern(err) => write!(f, "Invalid pattern: {}", err),
+            QuickfixError::Glob(err) => write!(f, "Invalid file pattern: {}", err),
+            QuickfixError::Io(err) => write!(f, "{}", err),
+            QuickfixError::NoMatch(pattern) => write!(f, "No match: {}", pattern),
+            QuickfixError::NoErrors => write!(f, "No Errors"),
+            QuickfixError::NoMoreItems => write!(f, "No more items"),
+        }
+    }
+}
+
+impl From<io::Error> for QuickfixError {
+    fn from(err: io::Error) -> Self {
+        QuickfixError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QuickfixEntry {
+    pub file: String,
+    pub line: u64,
+    pub column: u64,
+    pub text: String,
+}
+
+/// The result list of `:vimgrep` and `:grep`, browsed with `:cn`, `:cp`,
+/// `:cc` and `:copen`.
+#[derive(Debug, Default)]
+pub struct QuickfixList {
+    title: String,
+    entries: Vec<QuickfixEntry>,
+    current: usize,
+}
+
+impl QuickfixList {
+    pub fn new(title: String, entries: Vec<QuickfixEntry>) -> Self {
+        QuickfixList {
+            title,
+            entries,
+            current: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn index(&self) -> usize {
+        self.current
+    }
+
+    pub fn current(&self) -> Option<&QuickfixEntry> {
+        self.entries.get(self.current)
+    }
+
+    pub fn next(&mut self) -> Option<&QuickfixEntry> {
+        if self.current + 1 >= self.entries.len() {
+            return None;
+        }
+        self.current += 1;
+        self.current()
+    }
+
+    pub fn prev(&mut self) -> Option<&QuickfixEntry> {
+        if self.current == 0 {
+            return None;
+        }
+        self.current -= 1;
+        self.current()
+    }
+
+    pub fn select(&mut self, index: usize) -> Option<&QuickfixEntry> {
+        if index >= self.entries.len() {
+            return None;
+        }
+        self.current = index;
+        self.current()
+    }
+
+    pub fn render(&self, screen: &mut Screen, height: u16) {
+        let start = if self.current >= height as usize {
+            self.current + 1 - height as usize
+        } else {
+            0
+        };
+        for row in 0..height {
+            screen.clear_line(0, row);
+            let idx = start + row as usize;
+            if let Some(entry) = self.entries.get(idx) {
+                let line = format!(
+                    "{}|{} col {}| {}",
+                    entry.file,
+                    entry.line + 1,
+                    entry.column + 1,
+                    entry.text.trim()
+                );
+                let style = if idx == self.current {
+                    CellStyle::inverse()
+                } else {
+                    CellStyle::default()
+                };
+                screen.print(0, row, &line, style);
+            }
+        }
+        let title = format!(
+            "[Quickfix List] {} ({}/{})",
+            self.title,
+            self.current + 1,
+            self.entries.len()
+        );
+        screen.clear_line(0, height);
+        screen.print(0, height, &title, CellStyle::default());
+    }
+}
+
+pub type SearchResult = Result<Vec<QuickfixEntry>, QuickfixError>;
+
+/// Runs `search` on its own thread so that the editor stays responsive, the
+/// result is sent once it is done.
+pub fn spawn<F>(search: F) -> oneshot::Receiver<SearchResult>
+where
+    F: FnOnce() -> SearchResult + Send + 'static,
+{
+    let (tx, rx) = oneshot::channel();
+    thread::spawn(move || {
+        // The search was abandoned if the receiver is gone.
+        let _ = tx.send(search());
+    });
+    rx
+}
+
+/// Searches all files matching `files` for the regular expression `pattern`,
+/// reporting the first match of each line or all of them if `all` is set.
+pub fn vimgrep(pattern: &str, files: &[String], all: bool) -> SearchResult {
+    let regex = Regex::new(pattern).map_err(|err| QuickfixError::Pattern(err.to_string()))?;
+    let mut entries = Vec::new();
+    for files_glob in files {
+        let paths = glob(files_glob).map_err(|err| QuickfixError::Glob(err.to_string()))?;
+        for path in paths.filter_map(Result::ok).filter(|path| path.is_file()) {
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(err) => {
+                    debug!("skipping {:?}: {}", path, err);
+                    continue;
+                }
+            };
+            let file = path.to_string_lossy().into_owned();
+            for (line, text) in content.lines().enumerate() {
+                for found in regex.find_iter(text) {
+                    entries.push(QuickfixEntry {
+                        file: file.clone(),
+                        line: line as u64,
+                        column: found.start() as u64,
+                        text: text.to_owned(),
+                    });
+                    if !all {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    if entries.is_empty() {
+        return Err(QuickfixError::NoMatch(pattern.to_owned()));
+    }
+    Ok(entries)
+}
+
+/// Runs the external grep `program` with `args`, substituted for `$*` if the
+/// program contains it, and parses its `file:line[:column]:text` output.
+/// No shell is involved, quotes are handled by `split_words`.
+pub fn grep(program: &str, args: &str) -> SearchResult {
+    let cmd = if program.contains("$*") {
+        program.replace("$*", args)
+    } else {
+        format!("{} {}", program, args)
+    };
+    info!("running grep: {}", cmd);
+    let words = split_words(&cmd);
+    let (program, args) = match words.split_first() {
+        Some(words) => words,
+        None => return Err(QuickfixError::NoMatch(args.to_owned())),
+    };
+    let output = process::Command::new(program).args(args).output()?;
+    let entries: Vec<QuickfixEntry> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(parse_grep_line)
+        .collect();
+    if entries.is_empty() {
+        return Err(QuickfixError::NoMatch(args.join(" ")));
+    }
+    Ok(entries)
+}
+
+/// Splits a command line into words like a shell would, honoring single and
+/// double quotes and backslash escapes.
+fn split_words(cmd: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    // Whether a word was started, even if it is an empty quoted string.
+    let mut in_word = false;
+    let mut quote = None;
+    let mut chars = cmd.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"'), '\\') => match chars.next() {
+                Some(c) if c == '"' || c == '\\' => word.push(c),
+                Some(c) => {
+                    word.push('\\');
+                    word.push(c);
+                }
+                None => word.push('\\'),
+            },
+            (Some(_), c) => word.push(c),
+            (None, '\'') | (None, '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, '\\') => {
+                if let Some(c) = chars.next() {
+                    word.push(c);
+                }
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(word.split_off(0));
+                    in_word = false;
+                }
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    words
+}
+
+fn parse_grep_line(line: &str) -> Option<QuickfixEntry> {
+    let mut parts = line.splitn(4, ':');
+    let file = parts.next()?;
+    let lineno = parts.next()?.parse::<u64>().ok()?;
+    let rest = parts.next()?;
+    let (column, text) = match rest.parse::<u64>() {
+        Ok(column) => (column, parts.next().unwrap_or("").to_owned()),
+        Err(_) => match parts.next() {
+            Some(text) => (1, format!("{}:{}", rest, text)),
+            None => (1, rest.to_owned()),
+        },
+    };
+    Some(QuickfixEntry {
+        file: file.to_owned(),
+        line: lineno.saturating_sub(1),
+        column: column.saturating_sub(1),
+        text,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("vix-quickfix-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn splits_words_like_a_shell() {
+        assert_eq!(split_words("grep -rn  foo"), vec!["grep", "-rn", "foo"]);
+        assert_eq!(
+            split_words(r#"grep "two words" 'it''s' a\ b"#),
+            vec!["grep", "two words", "its", "a b"]
+        );
+        assert_eq!(
+            split_words(r#"a "say \"hi\"" '\n' """#),
+            vec!["a", r#"say "hi""#, r"\n", ""]
+        );
+    }
+
+    #[test]
+    fn parses_grep_output() {
+        let entry = parse_grep_line("src/main.rs:12:5:fn main() {").unwrap();
+        assert_eq!(
+            (entry.file.as_str(), entry.line, entry.column),
+            ("src/main.rs", 11, 4)
+        );
+        assert_eq!(entry.text, "fn main() {");
+
+        let entry = parse_grep_line("a.txt:3:key: value").unwrap();
+        assert_eq!((entry.line, entry.column), (2, 0));
+        assert_eq!(entry.text, "key: value");
+
+        assert!(parse_grep_line("Binary file matches").is_none());
+    }
+
+    #[test]
+    fn vimgrep_reports_first_or_all_matches() {
+        let dir = temp_dir("vimgrep");
+        fs::write(dir.join("a.txt"), "foo foo\nbar\nfoo\n").unwrap();
+        let files = [dir.join("*.txt").to_string_lossy().into_owned()];
+
+        let entries = vimgrep("fo+", &files, false).unwrap();
+        let found: Vec<_> = entries.iter().map(|e| (e.line, e.column)).collect();
+        assert_eq!(found, vec![(0, 0), (2, 0)]);
+
+        let entries = vimgrep("fo+", &files, true).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!((entries[1].line, entries[1].column), (0, 4));
+
+        assert!(match vimgrep("baz", &files, false) {
+            Err(QuickfixError::NoMatch(pattern)) => pattern == "baz",
+            _ => false,
+        });
+        assert!(matches!(
+            vimgrep("(", &files, false),
+            Err(QuickfixError::Pattern(_))
+        ));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn grep_passes_quoted_arguments() {
+        let dir = temp_dir("grep");
+        fs::write(dir.join("b.txt"), "one two\nthree \"two\"\n").unwrap();
+        let args = format!("'one two' {}", dir.display());
+        let entries = grep("grep -rn", &args).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].text, "one two");
+
+        let args = format!("-e '\"two\"' {}", dir.display());
+        let entries = grep("grep -rn $*", &args).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].line, 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn list_stays_within_its_entries() {
+        let entry = |line| QuickfixEntry {
+            file: "a".to_owned(),
+            line,
+            column: 0,
+            text: String::new(),
+        };
+        let mut list = QuickfixList::new("test".to_owned(), vec![entry(0), entry(1)]);
+        assert!(list.prev().is_none());
+        assert_eq!(list.next().map(|e| e.line), Some(1));
+        assert!(list.next().is_none());
+        assert_eq!(list.index(), 1);
+        assert!(list.select(2).is_none());
+        assert_eq!(list.select(0).map(|e| e.line), Some(0));
+    }
+}
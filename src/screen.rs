@@ -0,0 +1,395 @@
+use crate::backend::Backend;
+use crate::wrap::{self, LineLayout};
+use std::cmp;
+use std::io;
+use unicode_segmentation::UnicodeSegmentation;
+use xrl::Style;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Converts a color sent by xi-core, in ARGB format.
+    fn from_argb(argb_color: u32) -> Self {
+        let r = ((argb_color & 0x00ff_0000) >> 16) as u8;
+        let g = ((argb_color & 0x0000_ff00) >> 8) as u8;
+        let b = (argb_color & 0x0000_00ff) as u8;
+        Color::Rgb(r, g, b)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CellStyle {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+impl CellStyle {
+    pub fn fg(color: Color) -> Self {
+        CellStyle {
+            fg: Some(color),
+            ..CellStyle::default()
+        }
+    }
+
+    pub fn bold(self) -> Self {
+        CellStyle { bold: true, ..self }
+    }
+
+    pub fn inverse() -> Self {
+        CellStyle {
+            inverse: true,
+            ..CellStyle::default()
+        }
+    }
+
+    /// Converts a style defined by xi-core, the style 0 being the selection.
+    pub fn from_style(style: &Style) -> Self {
+        if style.id == 0 {
+            return CellStyle::inverse();
+        }
+        CellStyle {
+            fg: style.fg_color.map(Color::from_argb),
+            bg: style
+                .bg_color
+                .filter(|bg_color| *bg_color != 0)
+                .map(Color::from_argb),
+            bold: false,
+            italic: style.italic.unwrap_or(false),
+            underline: style.underline.unwrap_or(false),
+            inverse: false,
+        }
+    }
+
+    pub fn merge(self, other: CellStyle) -> Self {
+        CellStyle {
+            fg: other.fg.or(self.fg),
+            bg: other.bg.or(self.bg),
+            bold: self.bold || other.bold,
+            italic: self.italic || other.italic,
+            underline: self.underline || other.underline,
+            inverse: self.inverse || other.inverse,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    /// The grapheme shown, empty for the cell covered by a wide grapheme on
+    /// its left.
+    pub text: String,
+    pub style: CellStyle,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            text: " ".to_owned(),
+            style: CellStyle::default(),
+        }
+    }
+}
+
+/// An off-screen copy of the terminal. Frames are drawn into it, and only
+/// the cells that changed since the last frame are written out.
+pub struct Screen {
+    width: u16,
+    height: u16,
+    cells: Vec<Cell>,
+    /// The cells as last written to the terminal, `None` to redraw all.
+    shown: Option<Vec<Cell>>,
+    cursor: (u16, u16),
+    shown_cursor: Option<(u16, u16)>,
+}
+
+impl Screen {
+    pub fn new(width: u16, height: u16) -> Self {
+        Screen {
+            width,
+            height,
+            cells: vec![Cell::default(); width as usize * height as usize],
+            shown: None,
+            cursor: (0, 0),
+            shown_cursor: None,
+        }
+    }
+
+    pub fn size(&self) -> (u16, u16) {
+        (self.width, self.height)
+    }
+
+    /// Resizes the screen, which is redrawn entirely on the next flush.
+    pub fn resize(&mut self, width: u16, height: u16) {
+        *self = Screen::new(width, height);
+    }
+
+    fn cell(&self, x: u16, y: u16) -> Option<&Cell> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.cells
+            .get(y as usize * self.width as usize + x as usize)
+    }
+
+    fn cell_mut(&mut self, x: u16, y: u16) -> Option<&mut Cell> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        self.cells
+            .get_mut(y as usize * self.width as usize + x as usize)
+    }
+
+    pub fn set_cursor(&mut self, x: u16, y: u16) {
+        self.cursor = (x, y);
+    }
+
+    /// Writes `text` at column `x` of row `y`, cut at the right edge of the
+    /// screen, and returns the columns it takes. Tabs and control characters
+    /// are shown as in the text of a buffer.
+    pub fn print(&mut self, x: u16, y: u16, text: &str, style: CellStyle) -> u16 {
+        let layout = LineLayout::new(text, 0, wrap::TAB_WIDTH, None);
+        let mut column = x;
+        for (idx, grapheme) in text.graphemes(true).enumerate() {
+            let width = layout.width(idx);
+            if width == 0 {
+                // Combining characters go with the grapheme on their left.
+                if column > x {
+                    let left = self.previous_grapheme(column, y);
+                    if let Some(cell) = self.cell_mut(left, y) {
+                        cell.text.push_str(grapheme);
+                    }
+                }
+                continue;
+            }
+            if column + width > self.width {
+                break;
+            }
+            match wrap::control_char(grapheme) {
+                Some(c) => {
+                    for (offset, part) in wrap::caret_notation(c).chars().enumerate() {
+                        self.put(column + offset as u16, y, &part.to_string(), 1, style);
+                    }
+                }
+                None if grapheme == "\t" => self.clear(column, y, width, style),
+                None => self.put(column, y, grapheme, width, style),
+            }
+            column += width;
+        }
+        column - x
+    }
+
+    /// Sets the cells taken by a grapheme `width` columns wide, blanking
+    /// the rest of wide graphemes it overlaps.
+    fn put(&mut self, x: u16, y: u16, grapheme: &str, width: u16, style: CellStyle) {
+        if x > 0 && self.cell(x, y).is_some_and(|cell| cell.text.is_empty()) {
+            let left = self.previous_grapheme(x, y);
+            for column in left..x {
+                if let Some(cell) = self.cell_mut(column, y) {
+                    *cell = Cell::default();
+                }
+            }
+        }
+        let mut right = x + width;
+        while let Some(cell) = self.cell_mut(right, y) {
+            if !cell.text.is_empty() {
+                break;
+            }
+            *cell = Cell::default();
+            right += 1;
+        }
+        if let Some(cell) = self.cell_mut(x, y) {
+            cell.text = grapheme.to_owned();
+            cell.style = style;
+        }
+        for covered in x + 1..x + width {
+            if let Some(cell) = self.cell_mut(covered, y) {
+                cell.text = String::new();
+                cell.style = style;
+            }
+        }
+    }
+
+    fn previous_grapheme(&self, column: u16, y: u16) -> u16 {
+        let mut left = column - 1;
+        while left > 0 && self.cell(left, y).is_some_and(|cell| cell.text.is_empty()) {
+            left -= 1;
+        }
+        left
+    }
+
+    pub fn clear(&mut self, x: u16, y: u16, width: u16, style: CellStyle) {
+        let end = cmp::min(x.saturating_add(width), self.width);
+        for column in x..end {
+            self.put(column, y, " ", 1, style);
+        }
+    }
+
+    pub fn clear_line(&mut self, x: u16, y: u16) {
+        let width = self.width.saturating_sub(x);
+        self.clear(x, y, width, CellStyle::default());
+    }
+
+    /// Draws the cells changed since the last flush and places the cursor.
+    pub fn flush<B: Backend>(&mut self, backend: &mut B) -> io::Result<()> {
+        let redraw = self.shown.is_none();
+        if redraw {
+            backend.set_style(CellStyle::default())?;
+            backend.clear()?;
+        }
+        let blank = Cell::default();
+        let mut pen: Option<CellStyle> = None;
+        // Where the terminal cursor is after the last write.
+        let mut position: Option<(u16, u16)> = None;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let idx = y as usize * self.width as usize + x as usize;
+                let cell = &self.cells[idx];
+                let unchanged = match self.shown {
+                    Some(ref shown) => shown.get(idx) == Some(cell),
+                    // The cleared terminal already shows blank cells.
+                    None => *cell == blank,
+                };
+                if unchanged || cell.text.is_empty() {
+                    continue;
+                }
+                if position != Some((x, y)) {
+                    backend.goto(x, y)?;
+                }
+                if pen != Some(cell.style) {
+                    backend.set_style(cell.style)?;
+                    pen = Some(cell.style);
+                }
+                backend.write(&cell.text)?;
+                let width = cell
+                    .text
+                    .graphemes(true)
+                    .next()
+                    .map_or(1, |grapheme| wrap::grapheme_width(grapheme, 0, 1));
+                position = Some((x + width, y));
+            }
+        }
+        if pen.is_some() {
+            backend.set_style(CellStyle::default())?;
+        }
+        if position.is_some() || self.shown_cursor != Some(self.cursor) {
+            backend.goto(self.cursor.0, self.cursor.1)?;
+        }
+        self.shown = Some(self.cells.clone());
+        self.shown_cursor = Some(self.cursor);
+        backend.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::HeadlessBackend;
+
+    fn row(screen: &Screen, y: u16) -> Vec<&str> {
+        (0..screen.width)
+            .filter_map(|x| screen.cell(x, y))
+            .map(|cell| cell.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn wide_graphemes_that_do_not_fit_are_cut() {
+        let mut screen = Screen::new(4, 1);
+        assert_eq!(screen.print(2, 0, "a中", CellStyle::default()), 1);
+        assert_eq!(row(&screen, 0), vec![" ", " ", "a", " "]);
+        assert_eq!(screen.print(0, 0, "中中中", CellStyle::default()), 4);
+        assert_eq!(row(&screen, 0), vec!["中", "", "中", ""]);
+    }
+
+    #[test]
+    fn combining_characters_join_the_cell_on_their_left() {
+        let mut screen = Screen::new(3, 1);
+        assert_eq!(screen.print(0, 0, "e\u{301}x", CellStyle::default()), 2);
+        assert_eq!(row(&screen, 0), vec!["e\u{301}", "x", " "]);
+    }
+
+    #[test]
+    fn tabs_and_control_characters_are_shown_as_in_buffers() {
+        let mut backend = HeadlessBackend::new(8, 1);
+        let mut screen = Screen::new(8, 1);
+        assert_eq!(screen.print(0, 0, "a\tb\x1b", CellStyle::default()), 7);
+        assert_eq!(
+            row(&screen, 0),
+            vec!["a", " ", " ", " ", "b", "^", "[", " "]
+        );
+        screen.flush(&mut backend).unwrap();
+        assert_eq!(backend.row_text(0), "a   b^[ ");
+    }
+
+    #[test]
+    fn overwriting_half_of_a_wide_grapheme_blanks_the_other_half() {
+        let mut screen = Screen::new(4, 1);
+        screen.print(0, 0, "中中", CellStyle::default());
+        screen.print(1, 0, "x", CellStyle::default());
+        assert_eq!(row(&screen, 0), vec![" ", "x", "中", ""]);
+        screen.print(2, 0, "y", CellStyle::default());
+        assert_eq!(row(&screen, 0), vec![" ", "x", "y", " "]);
+    }
+
+    #[test]
+    fn flush_writes_only_the_changed_cells() {
+        let mut backend = HeadlessBackend::new(6, 2);
+        let mut screen = Screen::new(6, 2);
+        screen.print(0, 0, "abc", CellStyle::default());
+        screen.print(0, 1, "xyz", CellStyle::default());
+        screen.flush(&mut backend).unwrap();
+        assert_eq!(backend.clears(), 1);
+        assert_eq!(backend.row_text(0), "abc   ");
+        backend.take_writes();
+
+        screen.print(1, 0, "B", CellStyle::default());
+        screen.print(2, 1, "中", CellStyle::default());
+        screen.set_cursor(4, 1);
+        screen.flush(&mut backend).unwrap();
+        assert_eq!(
+            backend.take_writes(),
+            vec![((1, 0), "B".to_owned()), ((2, 1), "中".to_owned())]
+        );
+        assert_eq!(backend.clears(), 1);
+        assert_eq!(backend.row_text(0), "aBc   ");
+        assert_eq!(backend.row_text(1), "xy中  ");
+        assert_eq!(backend.cursor(), (4, 1));
+
+        screen.flush(&mut backend).unwrap();
+        assert_eq!(backend.take_writes(), vec![]);
+    }
+
+    #[test]
+    fn resize_redraws_everything() {
+        let mut backend = HeadlessBackend::new(4, 1);
+        let mut screen = Screen::new(4, 1);
+        screen.print(0, 0, "ab", CellStyle::default());
+        screen.flush(&mut backend).unwrap();
+        backend.take_writes();
+
+        backend.resize(3, 2);
+        screen.resize(3, 2);
+        screen.print(0, 0, "ab", CellStyle::default());
+        screen.print(0, 1, "c", CellStyle::default());
+        screen.flush(&mut backend).unwrap();
+        assert_eq!(backend.clears(), 2);
+        assert_eq!(
+            backend.take_writes(),
+            vec![
+                ((0, 0), "a".to_owned()),
+                ((1, 0), "b".to_owned()),
+                ((0, 1), "c".to_owned())
+            ]
+        );
+        assert_eq!(backend.row_text(0), "ab ");
+    }
+}
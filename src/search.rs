@@ -0,0 +1,191 @@
+use crate::options::Options;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchDirection {
+    Forward,
+    Backward,
+}
+
+impl SearchDirection {
+    pub fn reverse(self) -> Self {
+        match self {
+            SearchDirection::Forward => SearchDirection::Backward,
+            SearchDirection::Backward => SearchDirection::Forward,
+        }
+    }
+
+    pub fn delimiter(self) -> char {
+        match self {
+            SearchDirection::Forward => '/',
+            SearchDirection::Backward => '?',
+        }
+    }
+}
+
+/// Where to put the cursor relative to a match, i.e. the `e+1` in `/foo/e+1`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchOffset {
+    None,
+    Line(i64),
+    Start(i64),
+    End(i64),
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pub term: String,
+    pub direction: SearchDirection,
+    pub case_sensitive: bool,
+    pub regex: bool,
+    pub whole_words: bool,
+    pub offset: SearchOffset,
+}
+
+impl SearchQuery {
+    /// Parses the text typed after `/` or `?`, honoring the inline `\c`, `\C`,
+    /// `\v` and `\V` modifiers and a trailing offset.
+    pub fn parse(input: &str, direction: SearchDirection, options: &Options) -> Self {
+        let delimiter = direction.delimiter();
+        let mut term = String::with_capacity(input.len());
+        let mut case_sensitive = None;
+        let mut regex = options.regex;
+        let mut offset = SearchOffset::None;
+
+        let mut chars = input.char_indices();
+        while let Some((idx, c)) = chars.next() {
+            if c == delimiter {
+                offset = parse_offset(&input[idx + 1..]);
+                break;
+            }
+            if c != '\\' {
+                term.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some((_, 'c')) => case_sensitive = Some(false),
+                Some((_, 'C')) => case_sensitive = Some(true),
+                Some((_, 'v')) => regex = true,
+                Some((_, 'V')) => regex = false,
+                Some((_, c)) if c == delimiter => term.push(c),
+                Some((_, c)) => {
+                    term.push('\\');
+                    term.push(c);
+                }
+                None => term.push('\\'),
+            }
+        }
+
+        let case_sensitive = case_sensitive.unwrap_or_else(|| Self::case_sensitive(&term, options));
+        SearchQuery {
+            term,
+            direction,
+            case_sensitive,
+            regex,
+            whole_words: false,
+            offset,
+        }
+    }
+
+    /// A literal whole word search, as done by `*` and `#`.
+    pub fn word(word: &str, direction: SearchDirection, options: &Options) -> Self {
+        SearchQuery {
+            term: word.to_owned(),
+            direction,
+            case_sensitive: Self::case_sensitive(word, options),
+            regex: false,
+            whole_words: true,
+            offset: SearchOffset::None,
+        }
+    }
+
+    fn case_sensitive(term: &str, options: &Options) -> bool {
+        if !options.ignorecase {
+            return true;
+        }
+        options.smartcase && term.chars().any(char::is_uppercase)
+    }
+}
+
+fn parse_offset(offset: &str) -> SearchOffset {
+    let (kind, count): (fn(i64) -> SearchOffset, &str) = match offset.chars().next() {
+        Some('e') => (SearchOffset::End, &offset[1..]),
+        Some('s') | Some('b') => (SearchOffset::Start, &offset[1..]),
+        Some(_) => (SearchOffset::Line, offset),
+        None => return SearchOffset::None,
+    };
+    let count = match count {
+        "" => 0,
+        "+" => 1,
+        "-" => -1,
+        count => match count.trim_start_matches('+').parse::<i64>() {
+            Ok(count) => count,
+            Err(_) => {
+                warn!("invalid search offset '{}'", offset);
+                return SearchOffset::None;
+            }
+        },
+    };
+    kind(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> SearchQuery {
+        SearchQuery::parse(input, SearchDirection::Forward, &Options::default())
+    }
+
+    #[test]
+    fn parses_offsets() {
+        assert_eq!(parse("foo").offset, SearchOffset::None);
+        assert_eq!(parse("foo/").offset, SearchOffset::None);
+        assert_eq!(parse("foo/2").offset, SearchOffset::Line(2));
+        assert_eq!(parse("foo/-").offset, SearchOffset::Line(-1));
+        assert_eq!(parse("foo/e").offset, SearchOffset::End(0));
+        assert_eq!(parse("foo/e+1").offset, SearchOffset::End(1));
+        assert_eq!(parse("foo/s-2").offset, SearchOffset::Start(-2));
+        assert_eq!(parse("foo/b+").offset, SearchOffset::Start(1));
+        assert_eq!(parse("foo/x").offset, SearchOffset::None);
+    }
+
+    #[test]
+    fn escaped_delimiter_is_part_of_the_term() {
+        let query = parse(r"a\/b/e");
+        assert_eq!(query.term, "a/b");
+        assert_eq!(query.offset, SearchOffset::End(0));
+
+        let query = SearchQuery::parse(r"a/b?e", SearchDirection::Backward, &Options::default());
+        assert_eq!(query.term, "a/b");
+        assert_eq!(query.offset, SearchOffset::End(0));
+    }
+
+    #[test]
+    fn modifiers_override_options() {
+        let options = Options {
+            ignorecase: true,
+            ..Options::default()
+        };
+        let query = SearchQuery::parse(r"foo\C", SearchDirection::Forward, &options);
+        assert_eq!(query.term, "foo");
+        assert!(query.case_sensitive);
+        assert!(!parse(r"\cFoo").case_sensitive);
+
+        assert!(parse(r"\vfo+").regex);
+        assert!(!parse(r"\Vfo+").regex);
+        assert_eq!(parse(r"a\.b").term, r"a\.b");
+    }
+
+    #[test]
+    fn smartcase_needs_an_uppercase_letter() {
+        let options = Options {
+            ignorecase: true,
+            smartcase: true,
+            ..Options::default()
+        };
+        let parse = |input| SearchQuery::parse(input, SearchDirection::Forward, &options);
+        assert!(!parse("foo").case_sensitive);
+        assert!(parse("Foo").case_sensitive);
+        assert!(SearchQuery::word("Foo", SearchDirection::Forward, &options).case_sensitive);
+    }
+}
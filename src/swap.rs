@@ -0,0 +1,191 @@
+use crate::undo::Delta;
+use serde_derive::{Deserialize, Serialize};
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug)]
+pub enum SwapError {
+    NoStateDir,
+    Io(io::Error),
+    Format(serde_json::Error),
+}
+
+impl fmt::Display for SwapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SwapError::NoStateDir => write!(f, "No directory for state files"),
+            SwapError::Io(err) => write!(f, "{}", err),
+            SwapError::Format(err) => write!(f, "Invalid state file: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for SwapError {
+    fn from(err: io::Error) -> Self {
+        SwapError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SwapError {
+    fn from(err: serde_json::Error) -> Self {
+        SwapError::Format(err)
+    }
+}
+
+/// The unsaved changes of a buffer, written periodically so that they can
+/// be recovered after vix or xi-core crashed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Journal {
+    pub file: String,
+    pub pid: u32,
+    /// Seconds since the unix epoch.
+    pub time: u64,
+    /// The hash of the file the changes apply to, see `undo::file_hash`.
+    pub hash: u64,
+    pub deltas: Vec<Delta>,
+}
+
+impl Journal {
+    pub fn new(file: &str, hash: u64, deltas: Vec<Delta>) -> Self {
+        Journal {
+            file: file.to_owned(),
+            pid: process::id(),
+            time: now(),
+            hash,
+            deltas,
+        }
+    }
+
+    pub fn is_stale(&self) -> bool {
+        if self.pid == process::id() {
+            return false;
+        }
+        // Signal 0 only checks that the process exists, EPERM means it runs
+        // as another user.
+        let gone = unsafe { libc::kill(self.pid as libc::pid_t, 0) == -1 };
+        gone && io::Error::last_os_error().raw_os_error() == Some(libc::ESRCH)
+    }
+
+    pub fn describe(&self) -> String {
+        let minutes = now().saturating_sub(self.time) / 60;
+        let running = if self.is_stale() {
+            ""
+        } else {
+            ", still running"
+        };
+        format!(
+            "\"{}\" (written {} minutes ago by process {}{})",
+            self.file, minutes, self.pid, running
+        )
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+/// The directory for `kind` of state files, e.g. `~/.local/share/vix/swap`.
+pub fn state_dir(kind: &str) -> Result<PathBuf, SwapError> {
+    let mut dir = dirs::data_local_dir().ok_or(SwapError::NoStateDir)?;
+    dir.push("vix");
+    dir.push(kind);
+    Ok(dir)
+}
+
+/// The state file of `file` is named after its absolute path, with `/`
+/// replaced by `%`.
+pub fn state_path(kind: &str, file: &str, extension: &str) -> Result<PathBuf, SwapError> {
+    let path = match fs::canonicalize(file) {
+        Ok(path) => path,
+        Err(_) => env::current_dir()?.join(file),
+    };
+    let name = format!("{}.{}", path.to_string_lossy().replace('/', "%"), extension);
+    Ok(state_dir(kind)?.join(name))
+}
+
+fn journal_path(file: &str) -> Result<PathBuf, SwapError> {
+    state_path("swap", file, "swp")
+}
+
+pub fn write(journal: &Journal) -> Result<(), SwapError> {
+    let path = journal_path(&journal.file)?;
+    fs::create_dir_all(state_dir("swap")?)?;
+    // Write to a temporary file first so that a crash never leaves a
+    // truncated journal behind.
+    let tmp = path.with_extension("swp.tmp");
+    fs::write(&tmp, serde_json::to_vec(journal)?)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+pub fn read(file: &str) -> Result<Option<Journal>, SwapError> {
+    match fs::read(journal_path(file)?) {
+        Ok(content) => Ok(Some(serde_json::from_slice(&content)?)),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn remove(file: &str) -> Result<(), SwapError> {
+    match fs::remove_file(journal_path(file)?) {
+        Ok(()) => Ok(()),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub fn list() -> Result<Vec<Journal>, SwapError> {
+    let dir = state_dir("swap")?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut journals = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "swp") {
+            match fs::read(&path).map(|content| serde_json::from_slice(&content)) {
+                Ok(Ok(journal)) => journals.push(journal),
+                _ => warn!("skipping invalid recovery journal {:?}", path),
+            }
+        }
+    }
+    journals.sort_by(|a: &Journal, b: &Journal| a.file.cmp(&b.file));
+    Ok(journals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal(pid: u32) -> Journal {
+        Journal {
+            pid,
+            ..Journal::new("a.txt", 0, Vec::new())
+        }
+    }
+
+    #[test]
+    fn journal_of_a_running_process_is_not_stale() {
+        assert!(!journal(process::id()).is_stale());
+        // Init always runs, kill() fails with EPERM unless vix runs as root.
+        assert!(!journal(1).is_stale());
+    }
+
+    #[test]
+    fn journal_of_an_exited_process_is_stale() {
+        let mut child = process::Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        let journal = journal(pid);
+        assert!(journal.is_stale());
+        assert!(!journal.describe().contains("still running"));
+    }
+}
@@ -1,18 +1,32 @@
+use crate::backend::{Backend, TtyEvent};
+use crate::screen::{CellStyle, Color};
 use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
-use futures::{Async, Poll, Sink, Stream};
-use std::io::{self, Stdout};
-use std::thread::{sleep, spawn};
-use std::time::Duration;
+use futures::{Async, Future, Poll, Sink, Stream};
+use std::io::{self, Stdout, Write};
+use std::mem;
+use std::panic;
+use std::path::PathBuf;
+use std::process;
+use std::thread::spawn;
+use termion::clear;
+use termion::cursor::Goto;
 use termion::event::Event;
 use termion::input::{MouseTerminal, TermRead};
 use termion::raw::{IntoRawMode, RawTerminal};
-use termion::screen::AlternateScreen;
+use termion::screen::{AlternateScreen, ToAlternateScreen, ToMainScreen};
 use termion::terminal_size;
+use tokio_signal::unix::Signal;
 
-pub type RenderTarget = MouseTerminal<AlternateScreen<RawTerminal<Stdout>>>;
+/// Turns mouse reporting on and off, as `MouseTerminal` does.
+const ENTER_MOUSE_SEQUENCE: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h";
+const EXIT_MOUSE_SEQUENCE: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
+
+type RenderTarget = MouseTerminal<AlternateScreen<RawTerminal<Stdout>>>;
 
 pub struct Tty {
-    size: UnboundedReceiver<(u16, u16)>,
+    /// The size to report before waiting for a resize, e.g. at startup.
+    size: Option<(u16, u16)>,
+    resize: Box<dyn Stream<Item = i32, Error = io::Error> + Send>,
     stdin: UnboundedReceiver<Event>,
     stdout: RenderTarget,
 }
@@ -20,18 +34,17 @@ pub struct Tty {
 impl Tty {
     pub fn new() -> Result<Self, io::Error> {
         let (stdin_tx, stdin_rx) = unbounded();
-        let (size_tx, size_rx) = unbounded();
         let stdout = io::stdout().into_raw_mode()?;
         let stdout = MouseTerminal::from(AlternateScreen::from(stdout));
 
         let tty = Tty {
-            size: size_rx,
+            size: Some(terminal_size()?),
+            resize: Box::new(Signal::new(libc::SIGWINCH).flatten_stream()),
             stdin: stdin_rx,
             stdout,
         };
 
         Tty::start_stdin_listening(stdin_tx);
-        Tty::start_size_listening(size_tx);
 
         Ok(tty)
     }
@@ -42,72 +55,192 @@ impl Tty {
             for event in io::stdin().events() {
                 match event {
                     Ok(event) => {
-                        let _ = tx.start_send(event).unwrap();
-                        let _ = tx.poll_complete().unwrap();
+                        // vix is exiting if the receiver was dropped.
+                        if tx
+                            .start_send(event)
+                            .and_then(|_| tx.poll_complete())
+                            .is_err()
+                        {
+                            return;
+                        }
                     }
                     Err(err) => error!("failed to read tty event:  {}", err),
                 }
             }
         });
     }
-    fn start_size_listening(tx: UnboundedSender<(u16, u16)>) {
-        let mut tx = tx;
-        spawn(move || {
-            let mut current_size = (0, 0);
-            info!("waiting for resize events");
-            loop {
-                match terminal_size() {
-                    Ok(new_size) => {
-                        if new_size != current_size {
-                            info!(
-                                "terminal resized (from {:?} to {:?})",
-                                current_size, new_size
-                            );
-                            current_size = new_size;
-                            let _ = tx.start_send(current_size).unwrap();
-                            let _ = tx.poll_complete().unwrap();
-                        }
-                    }
-                    Err(e) => {
-                        error!("failed to get terminal size: {}", e);
+}
+
+fn write_color<W: Write>(w: &mut W, color: Color, background: bool) -> io::Result<()> {
+    fn write<W: Write, C: termion::color::Color>(
+        w: &mut W,
+        color: C,
+        background: bool,
+    ) -> io::Result<()> {
+        if background {
+            write!(w, "{}", termion::color::Bg(color))
+        } else {
+            write!(w, "{}", termion::color::Fg(color))
+        }
+    }
+    match color {
+        Color::Red => write(w, termion::color::Red, background),
+        Color::Green => write(w, termion::color::Green, background),
+        Color::Yellow => write(w, termion::color::Yellow, background),
+        Color::Rgb(r, g, b) => write(w, termion::color::Rgb(r, g, b), background),
+    }
+}
+
+impl Backend for Tty {
+    fn poll_event(&mut self) -> Poll<Option<TtyEvent>, ()> {
+        if let Some(size) = self.size.take() {
+            return Ok(Async::Ready(Some(TtyEvent::Resize(size))));
+        }
+        loop {
+            match self.resize.poll() {
+                Ok(Async::Ready(Some(_))) => match self.size() {
+                    Ok(size) => {
+                        info!("terminal resized to {:?}", size);
+                        return Ok(Async::Ready(Some(TtyEvent::Resize(size))));
                     }
+                    Err(err) => error!("failed to get terminal size: {}", err),
+                },
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(None)),
+                Ok(Async::NotReady) => break,
+                Err(err) => {
+                    error!("failed to wait for resize events: {}", err);
+                    return Err(());
                 }
-                sleep(Duration::from_millis(10));
             }
-        });
+        }
+        match self.stdin.poll() {
+            Ok(Async::Ready(Some(event))) => Ok(Async::Ready(Some(TtyEvent::Input(event)))),
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(()) => Err(()),
+        }
     }
 
-    pub fn stdout(&mut self) -> &mut RenderTarget {
-        &mut self.stdout
+    fn size(&self) -> io::Result<(u16, u16)> {
+        terminal_size()
     }
-}
 
-pub enum TtyEvent {
-    Resize((u16, u16)),
-    Input(Event),
-}
+    fn goto(&mut self, x: u16, y: u16) -> io::Result<()> {
+        write!(self.stdout, "{}", Goto(x + 1, y + 1))
+    }
+
+    fn set_style(&mut self, style: CellStyle) -> io::Result<()> {
+        write!(self.stdout, "{}", termion::style::Reset)?;
+        if style.bold {
+            write!(self.stdout, "{}", termion::style::Bold)?;
+        }
+        if style.italic {
+            write!(self.stdout, "{}", termion::style::Italic)?;
+        }
+        if style.underline {
+            write!(self.stdout, "{}", termion::style::Underline)?;
+        }
+        if style.inverse {
+            write!(self.stdout, "{}", termion::style::Invert)?;
+        }
+        if let Some(fg) = style.fg {
+            write_color(&mut self.stdout, fg, false)?;
+        }
+        if let Some(bg) = style.bg {
+            write_color(&mut self.stdout, bg, true)?;
+        }
+        Ok(())
+    }
 
-impl Stream for Tty {
-    type Item = TtyEvent;
-    type Error = ();
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.stdout.write_all(text.as_bytes())
+    }
 
-    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        match self.size.poll() {
-            Ok(Async::Ready(Some(size))) => {
-                return Ok(Async::Ready(Some(TtyEvent::Resize(size))));
-            }
-            Ok(Async::Ready(None)) => {
-                return Ok(Async::Ready(None));
-            }
-            Ok(Async::NotReady) => {}
-            Err(()) => return Err(()),
+    fn clear(&mut self) -> io::Result<()> {
+        write!(self.stdout, "{}", clear::All)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+
+    fn suspend(&mut self) -> io::Result<()> {
+        write!(self.stdout, "{}{}", EXIT_MOUSE_SEQUENCE, ToMainScreen)?;
+        self.stdout.flush()?;
+        self.stdout.suspend_raw_mode()?;
+        unsafe {
+            libc::raise(libc::SIGTSTP);
         }
-        match self.stdin.poll() {
-            Ok(Async::Ready(Some(event))) => return Ok(Async::Ready(Some(TtyEvent::Input(event)))),
-            Ok(Async::Ready(None)) => return Ok(Async::Ready(None)),
-            Ok(Async::NotReady) => {}
-            Err(()) => return Err(()),
+        // Stopped until SIGCONT.
+        self.stdout.activate_raw_mode()?;
+        write!(self.stdout, "{}{}", ToAlternateScreen, ENTER_MOUSE_SEQUENCE)?;
+        self.stdout.flush()?;
+        // Report the size as after a resize, to redraw everything.
+        self.size = Some(self.size()?);
+        Ok(())
+    }
+}
+
+fn terminal_mode() -> Option<libc::termios> {
+    unsafe {
+        let mut mode: libc::termios = mem::zeroed();
+        if libc::tcgetattr(libc::STDOUT_FILENO, &mut mode) == 0 {
+            Some(mode)
+        } else {
+            None
         }
-        Ok(Async::NotReady)
+    }
+}
+
+fn restore_terminal(mode: Option<&libc::termios>) {
+    let mut stdout = io::stdout();
+    let _ = write!(
+        stdout,
+        "{}{}{}",
+        termion::style::Reset,
+        EXIT_MOUSE_SEQUENCE,
+        ToMainScreen
+    );
+    let _ = stdout.flush();
+    if let Some(mode) = mode {
+        unsafe {
+            libc::tcsetattr(libc::STDOUT_FILENO, libc::TCSANOW, mode);
+        }
+    }
+}
+
+/// Puts the terminal back in the state it was in when the guard was created,
+/// when it is dropped and when vix panics, so that errors can be read.
+pub struct TerminalGuard {
+    mode: Option<libc::termios>,
+    log_file: PathBuf,
+}
+
+impl TerminalGuard {
+    pub fn new(log_file: PathBuf) -> Self {
+        let mode = terminal_mode();
+        let log = log_file.clone();
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal(mode.as_ref());
+            error!("vix panicked: {}", info);
+            default_hook(info);
+            eprintln!("vix crashed, see {} for details", log.display());
+            // The runtime would keep running without the editor.
+            process::exit(101);
+        }));
+        TerminalGuard { mode, log_file }
+    }
+
+    pub fn fatal(&self, msg: &str) {
+        restore_terminal(self.mode.as_ref());
+        eprintln!("vix: {}", msg);
+        eprintln!("See {} for details", self.log_file.display());
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal(self.mode.as_ref());
     }
 }
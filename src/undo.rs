@@ -0,0 +1,730 @@
+use crate::client::EditStep;
+use crate::swap::{self, SwapError};
+use serde_derive::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::mem;
+use xrl::{LineCache, Operation, OperationType};
+
+/// Maximum number of states kept per buffer, the oldest are dropped first.
+const UNDO_LEVELS: usize = 1000;
+
+/// Lines `start..start + old.len()` replaced with `new`, without their line
+/// breaks.
+#[derive(Debug, Clone, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Delta {
+    pub start: u64,
+    pub old: Vec<String>,
+    pub new: Vec<String>,
+}
+
+impl Delta {
+    pub fn invert(&self) -> Delta {
+        Delta {
+            start: self.start,
+            old: self.new.clone(),
+            new: self.old.clone(),
+        }
+    }
+
+    /// Combines this delta with `next`, made right after it, if the lines
+    /// they replace overlap or touch.
+    fn merge(&self, next: &Delta) -> Option<Delta> {
+        let end = self.start + self.new.len() as u64;
+        let next_end = next.start + next.old.len() as u64;
+        if next.start > end || next_end < self.start {
+            return None;
+        }
+        let lo = cmp::min(self.start, next.start);
+        let hi = cmp::max(end, next_end);
+        // The lines between `lo` and `hi` after this delta.
+        let between = |line: u64| {
+            if line >= self.start && line < end {
+                self.new[(line - self.start) as usize].clone()
+            } else {
+                next.old[(line - next.start) as usize].clone()
+            }
+        };
+        let mut old: Vec<String> = (lo..self.start).map(between).collect();
+        old.extend(self.old.iter().cloned());
+        old.extend((end..hi).map(between));
+        let mut new: Vec<String> = (lo..next.start).map(between).collect();
+        new.extend(next.new.iter().cloned());
+        new.extend((next_end..hi).map(between));
+        Some(Delta {
+            start: lo,
+            old,
+            new,
+        })
+    }
+
+    /// The edits making this delta in a buffer of `line_count` lines. The
+    /// last line has no line break, so changes reaching it select up to the
+    /// end of the buffer.
+    pub fn steps(&self, line_count: u64) -> Vec<EditStep> {
+        let (start, removed, added) = (self.start, self.old.len() as u64, self.new.len());
+        let mut steps = Vec::new();
+        if start + removed < line_count {
+            steps.push(EditStep::Click(start, 0));
+            if removed > 0 {
+                steps.push(EditStep::Drag(start + removed, 0));
+            }
+            if added > 0 {
+                let text: String = self.new.iter().map(|line| format!("{}\n", line)).collect();
+                steps.push(EditStep::Paste(text));
+            } else {
+                steps.push(EditStep::Delete);
+            }
+        } else if removed == 0 {
+            steps.push(EditStep::Click(line_count - 1, 0));
+            steps.push(EditStep::LineEnd);
+            steps.push(EditStep::Paste(format!("\n{}", self.new.join("\n"))));
+        } else {
+            let last = self.old.last().map_or(0, |line| line.len() as u64);
+            steps.push(EditStep::Click(start, 0));
+            steps.push(EditStep::Drag(line_count - 1, last));
+            if added > 0 {
+                steps.push(EditStep::Paste(self.new.join("\n")));
+            } else {
+                steps.push(EditStep::Delete);
+                if start > 0 {
+                    steps.push(EditStep::Backspace);
+                }
+            }
+        }
+        let line_count = line_count + added as u64 - removed;
+        steps.push(EditStep::Click(
+            cmp::min(start, line_count.saturating_sub(1)),
+            0,
+        ));
+        steps
+    }
+}
+
+/// The text of a line sent by xi-core, without its line break.
+fn line_text(text: &str) -> &str {
+    text.strip_suffix('\n').unwrap_or(text)
+}
+
+/// Lines removed and added by a run of operations between copied lines,
+/// `None` for lines that are not cached.
+#[derive(Default)]
+struct Hunk<'a> {
+    /// The first added line in the new document.
+    start: u64,
+    removed: Vec<Option<&'a str>>,
+    added: Vec<Option<&'a str>>,
+}
+
+impl<'a> Hunk<'a> {
+    /// Returns the lines whose text changed. Lines scrolled in or out of the
+    /// cache and lines resent for a new cursor position are left out, as
+    /// are lines unknown on one side when the other is known. `Err` if a
+    /// changed line is not cached.
+    fn delta(&self) -> Result<Option<Delta>, ()> {
+        let same = |(removed, added): (&Option<&str>, &Option<&str>)| match (removed, added) {
+            (Some(removed), Some(added)) => removed == added,
+            _ => true,
+        };
+        let prefix = self
+            .removed
+            .iter()
+            .zip(&self.added)
+            .take_while(|pair| same(*pair))
+            .count();
+        let max_suffix = cmp::min(self.removed.len(), self.added.len()) - prefix;
+        let suffix = self
+            .removed
+            .iter()
+            .rev()
+            .zip(self.added.iter().rev())
+            .take(max_suffix)
+            .take_while(|pair| same(*pair))
+            .count();
+        let old = &self.removed[prefix..self.removed.len() - suffix];
+        let new = &self.added[prefix..self.added.len() - suffix];
+        if old.is_empty() && new.is_empty() {
+            return Ok(None);
+        }
+        let known = |lines: &[Option<&str>]| -> Result<Vec<String>, ()> {
+            lines
+                .iter()
+                .map(|line| line.map(str::to_owned).ok_or(()))
+                .collect()
+        };
+        Ok(Some(Delta {
+            start: self.start + prefix as u64,
+            old: known(old)?,
+            new: known(new)?,
+        }))
+    }
+}
+
+/// Returns the changes made to the text by an update, given the cache it
+/// applies to, in the order they apply. `Err` if lines that changed are not
+/// cached, so that the change can't be undone.
+pub fn deltas(operations: &[Operation], cache: &LineCache) -> Result<Vec<Delta>, ()> {
+    let mut deltas = Vec::new();
+    // The first update fills an empty cache with the opened file.
+    if cache.before() + cache.lines().len() as u64 + cache.after() == 0 {
+        return Ok(deltas);
+    }
+    let cached = |line: u64| {
+        line.checked_sub(cache.before())
+            .and_then(|idx| cache.lines().get(idx as usize))
+            .map(|line| line.text.as_str())
+    };
+    let (mut old, mut new) = (0, 0);
+    let mut hunk = Hunk::default();
+    for op in operations {
+        let count = op.nb_lines;
+        match op.operation_type {
+            OperationType::Copy | OperationType::Update => {
+                deltas.extend(hunk.delta()?);
+                old += count;
+                new += count;
+                hunk = Hunk {
+                    start: new,
+                    ..Hunk::default()
+                };
+            }
+            OperationType::Skip => {
+                hunk.removed.extend((old..old + count).map(cached));
+                old += count;
+            }
+            OperationType::Insert => {
+                hunk.added
+                    .extend(op.lines.iter().map(|line| Some(line_text(&line.text))));
+                new += count;
+            }
+            OperationType::Invalidate => {
+                hunk.added.extend((0..count).map(|_| None));
+                new += count;
+            }
+        }
+    }
+    deltas.extend(hunk.delta()?);
+    Ok(deltas)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UndoState {
+    /// The changes from the parent state to this one.
+    deltas: Vec<Delta>,
+    /// Seconds since the unix epoch.
+    pub time: u64,
+    parent: Option<usize>,
+    /// The most recent state made from this one.
+    child: Option<usize>,
+}
+
+/// The undo tree of a buffer. States are stored in the order they were
+/// made, so that `g-` and `g+` can walk them chronologically while `u` and
+/// `Ctrl-r` follow the branches. Moving between states returns the deltas
+/// to apply to the buffer.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UndoHistory {
+    states: Vec<UndoState>,
+    current: usize,
+    /// The state of the text last saved, if it is in the tree.
+    #[serde(skip)]
+    saved: Option<usize>,
+    /// Changes made since the current state.
+    #[serde(skip)]
+    pending: Vec<Delta>,
+}
+
+impl UndoHistory {
+    /// Starts a history whose root is the current text, which is not known
+    /// to be saved.
+    pub fn new(time: u64) -> Self {
+        UndoHistory {
+            states: vec![UndoState {
+                deltas: Vec::new(),
+                time,
+                parent: None,
+                child: None,
+            }],
+            current: 0,
+            saved: None,
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn current(&self) -> Option<&UndoState> {
+        self.states.get(self.current)
+    }
+
+    /// The number of the current state, as shown to the user.
+    pub fn seq(&self) -> usize {
+        self.current
+    }
+
+    pub fn push(&mut self, delta: Delta) {
+        let merged = self.pending.last().and_then(|last| last.merge(&delta));
+        match merged {
+            Some(merged) => {
+                self.pending.pop();
+                if merged.old != merged.new {
+                    self.pending.push(merged);
+                }
+            }
+            None => self.pending.push(delta),
+        }
+    }
+
+    /// Makes the changes since the current state a new state.
+    pub fn checkpoint(&mut self, time: u64) {
+        if self.pending.is_empty() || self.states.is_empty() {
+            return;
+        }
+        let parent = self.current;
+        self.states.push(UndoState {
+            deltas: mem::take(&mut self.pending),
+            time,
+            parent: Some(parent),
+            child: None,
+        });
+        self.current = self.states.len() - 1;
+        self.states[parent].child = Some(self.current);
+        if self.states.len() > UNDO_LEVELS {
+            self.drop_oldest();
+        }
+    }
+
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.current) && self.pending.is_empty()
+    }
+
+    pub fn set_saved(&mut self) {
+        self.saved = Some(self.current);
+    }
+
+    /// Forgets the saved state, after saving text that is not in the tree.
+    pub fn clear_saved(&mut self) {
+        self.saved = None;
+    }
+
+    /// Drops the root. The state following it on the path to the current
+    /// state becomes the new root, and the branches left are dropped.
+    fn drop_oldest(&mut self) {
+        let mut root = self.current;
+        while let Some(parent) = self.states[root].parent {
+            if parent == 0 {
+                break;
+            }
+            root = parent;
+        }
+        if root == 0 {
+            return;
+        }
+        let kept: Vec<bool> = (0..self.states.len())
+            .map(|mut idx| loop {
+                if idx == root {
+                    break true;
+                }
+                match self.states[idx].parent {
+                    Some(parent) => idx = parent,
+                    None => break false,
+                }
+            })
+            .collect();
+        let mut indices = HashMap::new();
+        for (idx, _) in kept.iter().enumerate().filter(|(_, kept)| **kept) {
+            indices.insert(idx, indices.len());
+        }
+        let map = |idx: Option<usize>| idx.and_then(|idx| indices.get(&idx).cloned());
+        let states = mem::take(&mut self.states);
+        self.states = states
+            .into_iter()
+            .zip(kept)
+            .filter(|(_, kept)| *kept)
+            .map(|(state, _)| UndoState {
+                parent: map(state.parent),
+                child: map(state.child),
+                ..state
+            })
+            .collect();
+        self.states[0].deltas.clear();
+        self.current = indices[&self.current];
+        self.saved = map(self.saved);
+    }
+
+    fn path(&self, from: usize, to: usize) -> Vec<Delta> {
+        let ancestors = |mut idx: usize| {
+            let mut ancestors = vec![idx];
+            while let Some(parent) = self.states[idx].parent {
+                ancestors.push(parent);
+                idx = parent;
+            }
+            ancestors
+        };
+        let (up, down) = (ancestors(from), ancestors(to));
+        let common = up.iter().position(|idx| down.contains(idx)).unwrap_or(0);
+        let down_to = down.iter().position(|idx| *idx == up[common]).unwrap_or(0);
+        let mut deltas: Vec<Delta> = up[..common]
+            .iter()
+            .flat_map(|idx| self.states[*idx].deltas.iter().rev().map(Delta::invert))
+            .collect();
+        for idx in down[..down_to].iter().rev() {
+            deltas.extend(self.states[*idx].deltas.iter().cloned());
+        }
+        deltas
+    }
+
+    /// The changes from the saved text to the current one, `None` if the
+    /// saved state is not in the tree any more.
+    pub fn unsaved(&self) -> Option<Vec<Delta>> {
+        let mut deltas = self.path(self.saved?, self.current);
+        deltas.extend(self.pending.iter().cloned());
+        Some(deltas)
+    }
+
+    fn goto(&mut self, idx: usize) -> Option<Vec<Delta>> {
+        if idx == self.current {
+            return None;
+        }
+        let deltas = self.path(self.current, idx);
+        self.current = idx;
+        Some(deltas)
+    }
+
+    /// Moves to the parent state, for `u`.
+    pub fn undo(&mut self) -> Option<Vec<Delta>> {
+        let parent = self.current()?.parent?;
+        self.goto(parent)
+    }
+
+    /// Moves to the most recent child state, for `Ctrl-r`.
+    pub fn redo(&mut self) -> Option<Vec<Delta>> {
+        let child = self.current()?.child?;
+        self.goto(child)
+    }
+
+    /// Moves `count` states back or forward in the order they were made.
+    pub fn step(&mut self, count: i64) -> Option<Vec<Delta>> {
+        if self.states.is_empty() {
+            return None;
+        }
+        let last = self.states.len() as i64 - 1;
+        let idx = cmp::max(0, cmp::min(last, self.current as i64 + count)) as usize;
+        self.goto(idx)
+    }
+
+    /// Moves back or forward in time by `seconds`, to the last state made
+    /// before or the first one made after.
+    pub fn travel(&mut self, seconds: i64) -> Option<Vec<Delta>> {
+        let time = self.current()?.time as i64 + seconds;
+        let idx = if seconds < 0 {
+            self.states
+                .iter()
+                .rposition(|state| state.time as i64 <= time)
+                .unwrap_or(0)
+        } else {
+            self.states
+                .iter()
+                .position(|state| state.time as i64 >= time)
+                .unwrap_or(self.states.len() - 1)
+        };
+        self.goto(idx)
+    }
+}
+
+/// The undo history as stored on disk, valid as long as the file still
+/// has the content it was saved with.
+#[derive(Serialize)]
+struct UndoFile<'a> {
+    hash: u64,
+    history: &'a UndoHistory,
+}
+
+#[derive(Deserialize)]
+struct LoadedUndoFile {
+    hash: u64,
+    history: UndoHistory,
+}
+
+/// A hash of the file content that is stable across vix versions (FNV-1a).
+fn content_hash(content: &[u8]) -> u64 {
+    content.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Returns the hash of the content of `file`, a new file being empty.
+pub fn file_hash(file: &str) -> io::Result<u64> {
+    match fs::read(file) {
+        Ok(content) => Ok(content_hash(&content)),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(content_hash(&[])),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn store(file: &str, history: &UndoHistory) -> Result<(), SwapError> {
+    let undo_file = UndoFile {
+        hash: file_hash(file)?,
+        history,
+    };
+    fs::create_dir_all(swap::state_dir("undo")?)?;
+    let path = swap::state_path("undo", file, "json")?;
+    fs::write(path, serde_json::to_vec(&undo_file)?)?;
+    Ok(())
+}
+
+/// Loads the history of `file`, unless it was changed since it was stored.
+pub fn load(file: &str) -> Result<Option<UndoHistory>, SwapError> {
+    let content = match fs::read(swap::state_path("undo", file, "json")?) {
+        Ok(content) => content,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let undo_file: LoadedUndoFile = serde_json::from_slice(&content)?;
+    if undo_file.hash == file_hash(file)? {
+        let mut history = undo_file.history;
+        history.set_saved();
+        Ok(Some(history))
+    } else {
+        info!("ignoring undo history of {}, the file changed", file);
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use xrl::{Update, ViewId};
+
+    fn delta(start: u64, old: &[&str], new: &[&str]) -> Delta {
+        Delta {
+            start,
+            old: old.iter().map(|line| line.to_string()).collect(),
+            new: new.iter().map(|line| line.to_string()).collect(),
+        }
+    }
+
+    fn apply(lines: &mut Vec<String>, delta: &Delta) {
+        let start = delta.start as usize;
+        let removed: Vec<String> = lines
+            .splice(start..start + delta.old.len(), delta.new.iter().cloned())
+            .collect();
+        assert_eq!(removed, delta.old);
+    }
+
+    fn text(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    fn cache(lines: &[&str]) -> LineCache {
+        let mut cache = LineCache::default();
+        let lines: Vec<_> = lines
+            .iter()
+            .map(|line| json!({ "text": format!("{}\n", line) }))
+            .collect();
+        cache.update(Update {
+            rev: None,
+            operations: ops(json!([{ "op": "ins", "n": lines.len(), "lines": lines }])),
+            pristine: true,
+            view_id: ViewId(0),
+        });
+        cache
+    }
+
+    fn ops(ops: serde_json::Value) -> Vec<Operation> {
+        serde_json::from_value(ops).unwrap()
+    }
+
+    #[test]
+    fn deltas_leave_out_unchanged_and_uncached_lines() {
+        let cache = cache(&["a", "b", "c", "d"]);
+        let changed = ops(json!([
+            { "op": "copy", "n": 1 },
+            { "op": "skip", "n": 2 },
+            { "op": "ins", "n": 3, "lines": [{ "text": "b\n" }, { "text": "x\n" }, { "text": "y\n" }] },
+            { "op": "copy", "n": 1 },
+        ]));
+        assert_eq!(
+            deltas(&changed, &cache),
+            Ok(vec![delta(2, &["c"], &["x", "y"])])
+        );
+        let scrolled = ops(json!([
+            { "op": "skip", "n": 2 },
+            { "op": "invalidate", "n": 2 },
+            { "op": "copy", "n": 2 },
+        ]));
+        assert_eq!(deltas(&scrolled, &cache), Ok(vec![]));
+        let uncached = ops(json!([
+            { "op": "skip", "n": 4 },
+            { "op": "invalidate", "n": 5 },
+        ]));
+        assert_eq!(deltas(&uncached, &cache), Err(()));
+    }
+
+    #[test]
+    fn deltas_of_an_empty_cache_are_the_opened_file() {
+        let opened = ops(json!([{ "op": "ins", "n": 1, "lines": [{ "text": "a" }] }]));
+        assert_eq!(deltas(&opened, &LineCache::default()), Ok(vec![]));
+    }
+
+    #[test]
+    fn touching_deltas_merge() {
+        let typed = delta(1, &["b"], &["bx"]).merge(&delta(1, &["bx"], &["bxy"]));
+        assert_eq!(typed, Some(delta(1, &["b"], &["bxy"])));
+        let joined = delta(1, &["b"], &["b", ""]).merge(&delta(2, &[""], &["c"]));
+        assert_eq!(joined, Some(delta(1, &["b"], &["b", "c"])));
+        let before = delta(2, &["c"], &["x"]).merge(&delta(1, &["b"], &[]));
+        assert_eq!(before, Some(delta(1, &["b", "c"], &["x"])));
+        assert_eq!(delta(0, &["a"], &["x"]).merge(&delta(3, &["d"], &[])), None);
+    }
+
+    #[test]
+    fn merged_deltas_make_the_same_change() {
+        let original = text(&["a", "b", "c", "d"]);
+        let (first, second) = (
+            delta(1, &["b", "c"], &["x"]),
+            delta(0, &["a", "x"], &["y", "z", "w"]),
+        );
+        let mut expected = original.clone();
+        apply(&mut expected, &first);
+        apply(&mut expected, &second);
+        let mut merged = original.clone();
+        apply(&mut merged, &first.merge(&second).unwrap());
+        assert_eq!(merged, expected);
+        apply(&mut merged, &first.merge(&second).unwrap().invert());
+        assert_eq!(merged, original);
+    }
+
+    #[test]
+    fn undo_and_redo_follow_the_branches() {
+        let mut lines = text(&["a"]);
+        let mut history = UndoHistory::new(0);
+        history.push(delta(0, &["a"], &["b"]));
+        history.checkpoint(1);
+        history.push(delta(0, &["b"], &["c"]));
+        history.checkpoint(2);
+        apply(&mut lines, &delta(0, &["a"], &["c"]));
+        for delta in history.undo().unwrap() {
+            apply(&mut lines, &delta);
+        }
+        assert_eq!(lines, text(&["b"]));
+        for delta in history.undo().unwrap() {
+            apply(&mut lines, &delta);
+        }
+        assert_eq!(lines, text(&["a"]));
+        assert_eq!(history.undo(), None);
+        history.push(delta(0, &["a"], &["a", "d"]));
+        history.checkpoint(3);
+        apply(&mut lines, &delta(0, &["a"], &["a", "d"]));
+        // `g-` goes back to the state made before, on the other branch.
+        for delta in history.step(-1).unwrap() {
+            apply(&mut lines, &delta);
+        }
+        assert_eq!(lines, text(&["c"]));
+        for delta in history.travel(1).unwrap() {
+            apply(&mut lines, &delta);
+        }
+        assert_eq!(lines, text(&["a", "d"]));
+        assert_eq!(history.redo(), None);
+    }
+
+    #[test]
+    fn unsaved_changes_lead_from_the_saved_state() {
+        let mut history = UndoHistory::new(0);
+        history.push(delta(0, &["a"], &["b"]));
+        history.checkpoint(1);
+        history.set_saved();
+        history.push(delta(1, &[], &["c"]));
+        assert_eq!(history.unsaved(), Some(vec![delta(1, &[], &["c"])]));
+        history.checkpoint(2);
+        history.undo();
+        history.undo();
+        assert_eq!(history.unsaved(), Some(vec![delta(0, &["b"], &["a"])]));
+    }
+
+    #[test]
+    fn dropping_the_oldest_state_keeps_the_current_branch() {
+        let mut history = UndoHistory::new(0);
+        for time in 1..=UNDO_LEVELS as u64 {
+            let (old, new) = (time.to_string(), (time + 1).to_string());
+            history.push(delta(0, &[&old], &[&new]));
+            history.checkpoint(time);
+        }
+        assert_eq!(history.states.len(), UNDO_LEVELS);
+        assert_eq!(
+            history.current().map(|state| state.time),
+            Some(UNDO_LEVELS as u64)
+        );
+        assert_eq!(history.saved, None);
+        let mut lines = text(&[&(UNDO_LEVELS + 1).to_string()]);
+        while let Some(deltas) = history.undo() {
+            for delta in deltas {
+                apply(&mut lines, &delta);
+            }
+        }
+        assert_eq!(lines, text(&["2"]));
+    }
+
+    /// Applies the edits of `delta` to `lines`, emulating xi-core.
+    fn edit(lines: &[&str], delta: &Delta) -> Vec<String> {
+        let mut text = lines.join("\n");
+        let offset = |text: &str, line: u64, column: u64| {
+            let start: usize = text
+                .split('\n')
+                .take(line as usize)
+                .map(|line| line.len() + 1)
+                .sum();
+            start + column as usize
+        };
+        let mut selection = 0..0;
+        for step in delta.steps(lines.len() as u64) {
+            match step {
+                EditStep::Click(line, column) => {
+                    let at = offset(&text, line, column);
+                    selection = at..at;
+                }
+                EditStep::Drag(line, column) => selection.end = offset(&text, line, column),
+                EditStep::LineEnd => {
+                    let end = text[selection.end..]
+                        .find('\n')
+                        .map_or(text.len(), |idx| selection.end + idx);
+                    selection = end..end;
+                }
+                EditStep::Paste(pasted) => {
+                    text.replace_range(selection.clone(), &pasted);
+                    let end = selection.start + pasted.len();
+                    selection = end..end;
+                }
+                EditStep::Delete => {
+                    text.replace_range(selection.clone(), "");
+                    selection.end = selection.start;
+                }
+                EditStep::Backspace => {
+                    text.remove(selection.start - 1);
+                    selection = selection.start - 1..selection.start - 1;
+                }
+            }
+        }
+        text.split('\n').map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn steps_make_the_delta() {
+        let lines = ["a", "b", "c"];
+        let deltas = [
+            delta(1, &["b"], &["x", "y"]),
+            delta(1, &[], &["x"]),
+            delta(0, &["a", "b"], &[]),
+            delta(3, &[], &["x"]),
+            delta(1, &["b", "c"], &["x"]),
+            delta(2, &["c"], &[]),
+            delta(0, &["a", "b", "c"], &[""]),
+        ];
+        for delta in &deltas {
+            let mut expected = text(&lines);
+            apply(&mut expected, delta);
+            assert_eq!(edit(&lines, delta), expected, "{:?}", delta);
+        }
+    }
+}
@@ -1,70 +1,314 @@
-use crate::client::Client;
-use crate::style::{reset_style, set_style};
-use crate::window::Window;
+use crate::client::{Client, ClientResult, EditStep};
+use crate::marks::{self, ChangeList};
+use crate::options::{LineNumbers, Options};
+use crate::screen::{CellStyle, Color, Screen};
+use crate::search::{SearchDirection, SearchOffset, SearchQuery};
+use crate::swap;
+use crate::undo::{self, Delta, UndoHistory, UndoState};
+use crate::window::{Scroll, Window};
+use crate::wrap::{self, LineLayout, Wrap};
+use futures::{Async, Future};
 use serde_json::Value;
+use std::cmp;
 use std::collections::HashMap;
-use std::io::Write;
-//use termion;
-use termion::clear::CurrentLine;
-use termion::color;
-use termion::cursor::Goto;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+use std::time::SystemTime;
 use termion::event::{Event, Key, MouseButton, MouseEvent};
-use termion::style::{Bold, Reset};
-use xrl::{ClientResult, Line, LineCache, ModifySelection, Style, Update};
+use unicode_segmentation::UnicodeSegmentation;
+use xrl::{FindStatus, Line, LineCache, ModifySelection, Style, Update};
 
-#[derive(Debug, Default, Clone)]
+/// The style xi-core highlights search matches with.
+const FIND_STYLE: u64 = 1;
+
+/// Shown when an undo command can't use the undo tree.
+const DETACHED_ERROR: &str = "Undo history does not match the text";
+
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct Cursor {
     pub line: u64,
     pub column: u64,
 }
 
+/// The modification time and size of a file, used to notice changes made
+/// by other programs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct DiskState {
+    modified: SystemTime,
+    len: u64,
+}
+
+impl DiskState {
+    fn read(file: &str) -> Option<Self> {
+        let metadata = fs::metadata(file).ok()?;
+        Some(DiskState {
+            modified: metadata.modified().ok()?,
+            len: metadata.len(),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FileChange {
+    Unchanged,
+    Changed,
+    Deleted,
+}
+
 pub struct View {
     cache: LineCache,
     cursor: Cursor,
-    window: Window,
     file: Option<String>,
     client: Client,
     gutter_size: u16,
     tab_width: u16,
+    line_numbers: LineNumbers,
+    wrap: Option<Wrap>,
+    scrolloff: u16,
+    sidescroll: u16,
+    sidescrolloff: u16,
+    find_status: Option<FindStatus>,
+    search_origin: Option<Cursor>,
+    search_wrap: Option<(Cursor, SearchDirection)>,
+    pending_search: Option<String>,
+    message: Option<String>,
+    error: Option<String>,
+    pristine: bool,
+    disk_state: Option<DiskState>,
+    disk_hash: Option<u64>,
+    /// Hash of the changes last written to the recovery journal.
+    journal: Option<u64>,
+    undo: UndoHistory,
+    /// Set once a change could not be recorded: the undo tree does not lead
+    /// to the text any more, so undo commands use xi-core's own history.
+    detached: bool,
+    /// The edits made by undo commands, the updates they cause are not
+    /// recorded as changes.
+    replay: Option<ClientResult<Value>>,
+    marks: HashMap<char, Cursor>,
+    changes: ChangeList,
 }
 
 impl View {
     pub fn new(client: Client, file: Option<String>) -> View {
+        let disk_state = file.as_ref().and_then(|file| DiskState::read(file));
+        let disk_hash = file.as_ref().and_then(|file| undo::file_hash(file).ok());
+        let mut undo = UndoHistory::new(swap::now());
+        undo.set_saved();
         View {
             client,
             cache: LineCache::default(),
             cursor: Default::default(),
-            window: Window::new(),
             file,
             gutter_size: 0,
-            tab_width: 4,
+            tab_width: wrap::TAB_WIDTH,
+            line_numbers: LineNumbers::Absolute,
+            wrap: Some(Wrap::default()),
+            scrolloff: 0,
+            sidescroll: 0,
+            sidescrolloff: 0,
+            find_status: None,
+            search_origin: None,
+            search_wrap: None,
+            pending_search: None,
+            message: None,
+            error: None,
+            pristine: true,
+            disk_state,
+            disk_hash,
+            journal: None,
+            undo,
+            detached: false,
+            replay: None,
+            marks: HashMap::new(),
+            changes: ChangeList::default(),
         }
     }
 
     pub fn update_cache(&mut self, update: Update) {
         debug!("updating cache");
-        self.cache.update(update)
+        self.pristine = update.pristine;
+        for mark in self.marks.values_mut() {
+            mark.line = marks::map_line(&update.operations, mark.line);
+        }
+        self.changes.update(&update.operations);
+        if let Some(change) = marks::find_change(&update.operations, &self.cache) {
+            self.changes.push(change);
+        }
+        if self.replay.is_none() && !self.detached {
+            self.record_changes(&update);
+        }
+        self.cache.update(update);
+    }
+
+    fn record_changes(&mut self, update: &Update) {
+        match undo::deltas(&update.operations, &self.cache) {
+            Ok(deltas) => {
+                for delta in deltas {
+                    self.undo.push(delta);
+                }
+            }
+            Err(()) => {
+                warn!("lines changed outside of the cache, undoing with xi-core from now on");
+                self.detach();
+            }
+        }
+    }
+
+    /// Keeps the undo tree as it is, without the changes it could not
+    /// record.
+    fn detach(&mut self) {
+        self.checkpoint();
+        self.detached = true;
+    }
+
+    /// Whether the buffer has changes that are not saved yet. Undoing back
+    /// to the saved text is not known to xi-core.
+    pub fn is_modified(&self) -> bool {
+        !self.pristine && (self.detached || !self.undo.is_saved())
+    }
+
+    /// Makes the current text the saved one, after xi-core reported the
+    /// buffer pristine.
+    pub fn mark_saved(&mut self) {
+        self.checkpoint();
+        if self.detached {
+            self.undo.clear_saved();
+        } else {
+            self.undo.set_saved();
+        }
+        self.disk_hash = self.file().and_then(|file| undo::file_hash(file).ok());
+    }
+
+    pub fn update_disk_state(&mut self) {
+        self.disk_state = self.file().and_then(DiskState::read);
+    }
+
+    /// Checks whether the file was changed on disk since it was last read,
+    /// written or checked.
+    pub fn check_disk_state(&mut self) -> FileChange {
+        let disk_state = match self.file() {
+            Some(file) => DiskState::read(file),
+            None => return FileChange::Unchanged,
+        };
+        if disk_state == self.disk_state {
+            return FileChange::Unchanged;
+        }
+        self.disk_state = disk_state;
+        match disk_state {
+            Some(_) => FileChange::Changed,
+            None => FileChange::Deleted,
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self.file.as_ref().map(Path::new).and_then(Path::file_name) {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => "[No Name]".to_owned(),
+        }
     }
 
     pub fn set_cursor(&mut self, line: u64, column: u64) {
+        if let Some((start, direction)) = self.search_wrap.take() {
+            let wrapped = match direction {
+                SearchDirection::Forward => (line, column) < (start.line, start.column),
+                SearchDirection::Backward => (line, column) > (start.line, start.column),
+            };
+            if wrapped {
+                self.message = Some(match direction {
+                    SearchDirection::Forward => "search hit BOTTOM, continuing at TOP".to_owned(),
+                    SearchDirection::Backward => "search hit TOP, continuing at BOTTOM".to_owned(),
+                });
+            }
+        }
         self.cursor = Cursor { line, column };
-        self.window.set_cursor(&self.cursor);
     }
 
-    pub fn render<W: Write>(&mut self, w: &mut W, styles: &HashMap<u64, Style>, state: &str) {
-        self.update_window();
-        self.render_lines(w, styles);
-        self.render_status(w, state);
-        self.render_cursor(w);
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
     }
 
-    pub fn resize(&mut self, height: u16) {
-        self.window.resize(height);
-        self.update_window();
-        self.client.scroll(
-            self.cache.before() + self.window.start(),
-            self.cache.after() + self.window.end(),
-        );
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+    }
+
+    pub fn clear_message(&mut self) {
+        self.message = None;
+    }
+
+    pub fn take_error(&mut self) -> Option<String> {
+        self.error.take()
+    }
+
+    pub fn set_find_status(&mut self, status: FindStatus) {
+        debug!("find status: {:?}", status);
+        self.find_status = Some(status);
+        if let Some(term) = self.pending_search.take() {
+            self.report_not_found(&term);
+        }
+    }
+
+    fn report_not_found(&mut self, term: &str) {
+        if let Some((_, 0)) = self.search_matches() {
+            self.error = Some(format!("Pattern not found: {}", term));
+        }
+    }
+
+    /// The match at or before the cursor and the number of matches. The
+    /// matches on the cursor line are told apart by their highlights.
+    pub fn search_matches(&self) -> Option<(u64, u64)> {
+        let query = self.find_status.as_ref()?.queries.first()?;
+        if query.matches == 0 {
+            return Some((0, 0));
+        }
+        let before = query
+            .lines
+            .iter()
+            .filter(|line| **line < self.cursor.line)
+            .count() as u64;
+        let on_line = self.cached_line(self.cursor.line).map_or(0, |line| {
+            style_ranges(line)
+                .filter(|(range, style_id)| {
+                    *style_id == FIND_STYLE && range.start as u64 <= self.cursor.column
+                })
+                .count() as u64
+        });
+        let current = cmp::max(1, before + on_line);
+        Some((cmp::min(current, query.matches), query.matches))
+    }
+
+    pub fn render(
+        &mut self,
+        screen: &mut Screen,
+        window: &mut Window,
+        styles: &HashMap<u64, Style>,
+        options: &Options,
+        state: &str,
+        active: bool,
+    ) {
+        self.line_numbers = options.line_numbers();
+        self.wrap = options.wrap();
+        self.scrolloff = options.scrolloff;
+        self.sidescroll = options.sidescroll;
+        self.sidescrolloff = options.sidescrolloff;
+        self.update_window(window);
+        self.render_lines(screen, window, styles);
+        self.render_status(screen, window, state);
+        if active {
+            self.render_cursor(screen, window);
+        }
+    }
+
+    pub fn resize(&mut self, window: &mut Window) {
+        self.update_window(window);
+        self.request_lines(window);
+    }
+
+    fn request_lines(&mut self, window: &Window) {
+        let before = self.cache.before();
+        self.client
+            .scroll(before + window.start(), before + window.end());
     }
 
     pub fn collapse_selections(&mut self) {
@@ -87,10 +331,169 @@ impl View {
         self.client.goto_line(line)
     }
 
+    pub fn goto(&mut self, line: u64, column: u64) {
+        self.client.click(line, column)
+    }
+
+    pub fn set_mark(&mut self, mark: char) {
+        self.marks.insert(mark, self.cursor.clone());
+    }
+
+    pub fn mark(&self, mark: char) -> Option<&Cursor> {
+        self.marks.get(&mark)
+    }
+
+    pub fn last_change(&self) -> Option<&Cursor> {
+        self.changes.last()
+    }
+
+    pub fn goto_change(&mut self, older: bool) {
+        let change = if older {
+            self.changes.older()
+        } else {
+            self.changes.newer()
+        };
+        match change.cloned() {
+            Some(change) => self.goto(change.line, change.column),
+            None if self.changes.is_empty() => self.error = Some("Change list is empty".into()),
+            None if older => self.error = Some("At start of change list".into()),
+            None => self.error = Some("At end of change list".into()),
+        }
+    }
+
+    /// Returns the column of the first non blank character of `line`, if the
+    /// line is cached.
+    pub fn first_non_blank(&self, line: u64) -> u64 {
+        self.cached_line(line).map_or(0, |line| {
+            line.text
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .count() as u64
+        })
+    }
+
+    pub fn line_count(&self) -> u64 {
+        self.cache.before() + self.cache.lines().len() as u64 + self.cache.after()
+    }
+
+    fn cached_line(&self, line: u64) -> Option<&Line> {
+        line.checked_sub(self.cache.before())
+            .and_then(|idx| self.cache.lines().get(idx as usize))
+    }
+
+    fn text_width(&self, window: &Window) -> u16 {
+        window.width().saturating_sub(self.gutter_size)
+    }
+
+    fn layout(&self, line: &Line, window: &Window) -> LineLayout {
+        LineLayout::new(
+            &line.text,
+            self.text_width(window),
+            self.tab_width,
+            self.wrap.as_ref(),
+        )
+    }
+
+    /// Moves the cursor to the next or previous screen row, for `gj` and
+    /// `gk`. Without `wrap` these are the next and previous lines.
+    pub fn move_display_line(&mut self, window: &Window, down: bool) {
+        let cursor = self.cursor.clone();
+        let line = match self.cached_line(cursor.line) {
+            Some(line) if self.wrap.is_some() => line,
+            _ => {
+                if down {
+                    self.client.down()
+                } else {
+                    self.client.up()
+                }
+                return;
+            }
+        };
+        let layout = self.layout(line, window);
+        let (row, column) = layout.position(cursor.column as usize);
+        let target = if down && row + 1 < layout.height() {
+            Some((cursor.line, layout.offset_at(row + 1, column)))
+        } else if !down && row > 0 {
+            Some((cursor.line, layout.offset_at(row - 1, column)))
+        } else {
+            let next = if down {
+                Some(cursor.line + 1)
+            } else {
+                cursor.line.checked_sub(1)
+            };
+            next.and_then(|next| {
+                let line = self.cached_line(next)?;
+                let layout = self.layout(line, window);
+                let row = if down { 0 } else { layout.height() - 1 };
+                Some((next, layout.offset_at(row, column)))
+            })
+        };
+        if let Some((line, column)) = target {
+            self.goto(line, column as u64);
+        }
+    }
+
+    pub fn search_origin(&self) -> Option<&Cursor> {
+        self.search_origin.as_ref()
+    }
+
+    /// Returns whether this view shows `file`, comparing canonical paths if
+    /// both exist.
+    pub fn is_file(&self, file: &str) -> bool {
+        match self.file {
+            Some(ref own) => match (fs::canonicalize(own), fs::canonicalize(file)) {
+                (Ok(own), Ok(other)) => own == other,
+                _ => own == file,
+            },
+            None => false,
+        }
+    }
+
     pub fn copy(&mut self) -> ClientResult<Value> {
         self.client.copy()
     }
 
+    pub fn recover(&mut self, deltas: &[Delta]) {
+        match self.edit_steps(deltas) {
+            Some(steps) => {
+                tokio::spawn(self.client.edit(steps).map(|_| ()).map_err(|_| ()));
+            }
+            None => self.error = Some("Recovery journal does not match the text".into()),
+        }
+    }
+
+    /// The changes since the text was last saved or opened, `None` if they
+    /// are not all in the undo history.
+    pub fn unsaved_changes(&self) -> Option<Vec<Delta>> {
+        if self.detached {
+            return None;
+        }
+        self.undo.unsaved()
+    }
+
+    pub fn disk_hash(&self) -> Option<u64> {
+        self.disk_hash
+    }
+
+    pub fn journal(&self) -> Option<u64> {
+        self.journal
+    }
+
+    pub fn set_journal(&mut self, journal: Option<u64>) {
+        self.journal = journal;
+    }
+
+    pub fn replace_char(&mut self, chr: char) {
+        // Selecting to the right at the end of a line would select the line
+        // break.
+        let under_cursor = self
+            .cached_line(self.cursor.line)
+            .is_some_and(|line| (self.cursor.column as usize) < line.text.len());
+        if under_cursor {
+            self.client.replace_char(chr);
+        }
+    }
+
     pub fn paste(&mut self, buffer: &str) {
         self.client.paste(buffer);
     }
@@ -99,106 +502,547 @@ impl View {
         self.client.cut()
     }
 
+    pub fn checkpoint(&mut self) {
+        self.undo.checkpoint(swap::now());
+    }
+
     pub fn undo(&mut self) {
-        self.client.undo();
+        if self.detached {
+            self.client.undo();
+            return;
+        }
+        self.checkpoint();
+        let seq = self.undo.seq();
+        match self.undo.undo() {
+            Some(deltas) => self.restore_undo_state(deltas, "before", seq),
+            None => self.message = Some(undo_limit_message(-1)),
+        }
     }
 
     pub fn redo(&mut self) {
-        self.client.redo();
+        if self.detached {
+            self.client.redo();
+            return;
+        }
+        self.checkpoint();
+        match self.undo.redo() {
+            Some(deltas) => {
+                let seq = self.undo.seq();
+                self.restore_undo_state(deltas, "after", seq);
+            }
+            None => self.message = Some(undo_limit_message(1)),
+        }
     }
 
-    pub fn find(
-        &mut self,
-        search_term: &str,
-        case_sensitive: bool,
-        regex: bool,
-        whole_words: bool,
-    ) {
-        self.client
-            .find(search_term, case_sensitive, regex, whole_words);
+    pub fn undo_step(&mut self, count: i64) {
+        if self.detached {
+            for _ in 0..count.abs() {
+                if count < 0 {
+                    self.client.undo();
+                } else {
+                    self.client.redo();
+                }
+            }
+            return;
+        }
+        self.checkpoint();
+        match self.undo.step(count) {
+            Some(deltas) => {
+                let seq = self.undo.seq();
+                self.restore_undo_state(deltas, "at", seq);
+            }
+            None => self.message = Some(undo_limit_message(count)),
+        }
     }
 
-    pub fn find_next(
-        &mut self,
-        wrap_around: bool,
-        allow_same: bool,
-        modify_selection: ModifySelection,
-    ) {
-        self.client
-            .find_next(wrap_around, allow_same, modify_selection);
+    pub fn undo_travel(&mut self, seconds: i64) {
+        if self.detached {
+            self.error = Some(DETACHED_ERROR.into());
+            return;
+        }
+        self.checkpoint();
+        match self.undo.travel(seconds) {
+            Some(deltas) => {
+                let seq = self.undo.seq();
+                self.restore_undo_state(deltas, "at", seq);
+            }
+            None => self.message = Some(undo_limit_message(seconds)),
+        }
     }
 
-    pub fn find_prev(
-        &mut self,
-        wrap_around: bool,
-        allow_same: bool,
-        modify_selection: ModifySelection,
-    ) {
-        self.client
-            .find_prev(wrap_around, allow_same, modify_selection);
+    fn restore_undo_state(&mut self, deltas: Vec<Delta>, position: &str, seq: usize) {
+        let message = match self.undo.current() {
+            Some(state) => describe_undo_state(position, seq, state),
+            None => return,
+        };
+        if self.apply(deltas) {
+            self.message = Some(message);
+        }
+    }
+
+    fn apply(&mut self, deltas: Vec<Delta>) -> bool {
+        match self.edit_steps(&deltas) {
+            Some(steps) => {
+                self.replay = Some(self.client.edit(steps));
+                self.poll_replay();
+                true
+            }
+            None => {
+                error!(
+                    "the buffer does not match the undo history, undoing with xi-core from now on"
+                );
+                self.detach();
+                self.error = Some(DETACHED_ERROR.into());
+                false
+            }
+        }
+    }
+
+    /// Returns the edits making `deltas`. The buffer must have the lines the
+    /// first delta replaces, so that changes made elsewhere are not undone
+    /// blindly.
+    fn edit_steps(&self, deltas: &[Delta]) -> Option<Vec<EditStep>> {
+        let matches = deltas.first().is_none_or(|delta| {
+            delta.old.iter().enumerate().all(|(idx, old)| {
+                self.cached_line(delta.start + idx as u64)
+                    .is_none_or(|line| line.text == *old)
+            })
+        });
+        if !matches {
+            return None;
+        }
+        let mut line_count = self.line_count();
+        let mut steps = Vec::new();
+        for delta in deltas {
+            steps.extend(delta.steps(line_count));
+            line_count = line_count + delta.new.len() as u64 - delta.old.len() as u64;
+        }
+        Some(steps)
+    }
+
+    /// Polls the edits made by an undo command, returns whether they are
+    /// done. Their updates may still have to be handled.
+    pub fn poll_replay(&mut self) -> bool {
+        match self.replay {
+            Some(ref mut replay) => match replay.poll() {
+                Ok(Async::NotReady) => false,
+                Ok(Async::Ready(_)) => true,
+                Err(err) => {
+                    error!("failed to apply undo changes: {:?}", err);
+                    true
+                }
+            },
+            None => true,
+        }
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    pub fn finish_replay(&mut self) {
+        self.replay = None;
+    }
+
+    /// The undo tree, `None` if it does not lead to the text.
+    pub fn undo_history(&self) -> Option<&UndoHistory> {
+        if self.detached {
+            return None;
+        }
+        Some(&self.undo)
+    }
+
+    pub fn set_undo_history(&mut self, undo: UndoHistory) {
+        self.undo = undo;
+    }
+
+    pub fn search(&mut self, query: &SearchQuery, wrap_around: bool, allow_same: bool) {
+        self.client.find(
+            &query.term,
+            query.case_sensitive,
+            query.regex,
+            query.whole_words,
+        );
+        self.pending_search = Some(query.term.clone());
+        self.track_search_wrap(query.direction);
+        self.find_match(query.direction, wrap_around, allow_same);
+        self.client.highlight_find(true);
+        self.apply_search_offset(query.offset);
     }
 
-    pub fn find_all(&mut self) {
-        self.client.find_all();
+    pub fn search_next(&mut self, query: &SearchQuery, reverse: bool, wrap_around: bool) {
+        let direction = if reverse {
+            query.direction.reverse()
+        } else {
+            query.direction
+        };
+        self.report_not_found(&query.term);
+        self.track_search_wrap(direction);
+        self.find_match(direction, wrap_around, false);
+        self.client.highlight_find(true);
+        self.apply_search_offset(query.offset);
     }
 
-    pub fn highlight_find(&mut self, visible: bool) {
-        self.client.highlight_find(visible);
+    /// Remembers where a search started, so wrapping around the end of the
+    /// file can be reported once the cursor moved to the match.
+    fn track_search_wrap(&mut self, direction: SearchDirection) {
+        let start = match self.search_origin {
+            Some(ref origin) => origin.clone(),
+            None => self.cursor.clone(),
+        };
+        self.search_wrap = Some((start, direction));
     }
 
-    pub fn save(&mut self) -> ClientResult<()> {
-        self.client.save(self.file.as_ref().unwrap())
+    pub fn incremental_find(&mut self, query: &SearchQuery, wrap_around: bool) {
+        if let Some(origin) = self.search_origin.clone() {
+            self.client.click(origin.line, origin.column);
+        }
+        if query.term.is_empty() {
+            self.find_status = None;
+            self.client.highlight_find(false);
+            return;
+        }
+        self.client.find(
+            &query.term,
+            query.case_sensitive,
+            query.regex,
+            query.whole_words,
+        );
+        self.find_match(query.direction, wrap_around, true);
+        self.client.highlight_find(true);
+    }
+
+    pub fn goto_declaration(&mut self, query: &SearchQuery, global: bool) {
+        let line = if global { 0 } else { self.function_start() };
+        self.client.click(line, 0);
+        self.search(query, false, true);
+        self.search_wrap = None;
     }
 
-    fn update_window(&mut self) {
+    /// Returns the closest line at or above the cursor that declares a
+    /// function, or the first line if there is none.
+    fn function_start(&self) -> u64 {
+        let before = self.cache.before();
+        if self.cursor.line < before {
+            return 0;
+        }
+        let cursor_idx = (self.cursor.line - before) as usize;
+        self.cache
+            .lines()
+            .iter()
+            .take(cursor_idx + 1)
+            .rposition(|line| line.text.split_whitespace().any(|word| word == "fn"))
+            .map_or(0, |idx| before + idx as u64)
+    }
+
+    pub fn word_under_cursor(&self) -> Option<String> {
         if self.cursor.line < self.cache.before() {
+            return None;
+        }
+        let line_idx = (self.cursor.line - self.cache.before()) as usize;
+        let text = &self.cache.lines().get(line_idx)?.text;
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        // The cursor column is a byte offset.
+        let column = self.cursor.column as usize;
+        let (start, _) = text
+            .char_indices()
+            .find(|(idx, c)| *idx >= column && is_word(*c))?;
+        let begin = text[..start]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !is_word(*c))
+            .map_or(0, |(idx, c)| idx + c.len_utf8());
+        let end = text[start..]
+            .char_indices()
+            .find(|(_, c)| !is_word(*c))
+            .map_or(text.len(), |(idx, _)| start + idx);
+        Some(text[begin..end].to_owned())
+    }
+
+    fn find_match(&mut self, direction: SearchDirection, wrap_around: bool, allow_same: bool) {
+        match direction {
+            SearchDirection::Forward => {
+                self.client
+                    .find_next(wrap_around, allow_same, ModifySelection::Set)
+            }
+            SearchDirection::Backward => {
+                self.client
+                    .find_prev(wrap_around, allow_same, ModifySelection::Set)
+            }
+        }
+    }
+
+    /// Moves the cursor from the selected match according to `offset`. Moving
+    /// left or right first collapses the selection to the start or the end
+    /// of the match.
+    fn apply_search_offset(&mut self, offset: SearchOffset) {
+        match offset {
+            SearchOffset::None => self.client.left(),
+            SearchOffset::Line(count) => {
+                self.client.left();
+                for _ in 0..count.abs() {
+                    if count > 0 {
+                        self.client.down();
+                    } else {
+                        self.client.up();
+                    }
+                }
+                self.client.home();
+            }
+            SearchOffset::Start(count) => {
+                self.client.left();
+                self.move_horizontal(count);
+            }
+            SearchOffset::End(count) => {
+                self.client.right();
+                self.client.left();
+                self.move_horizontal(count);
+            }
+        }
+    }
+
+    fn move_horizontal(&mut self, count: i64) {
+        for _ in 0..count.abs() {
+            if count > 0 {
+                self.client.right();
+            } else {
+                self.client.left();
+            }
+        }
+    }
+
+    pub fn save_search_origin(&mut self) {
+        self.search_origin = Some(self.cursor.clone());
+    }
+
+    pub fn restore_search_origin(&mut self) {
+        if let Some(origin) = self.search_origin.take() {
+            self.client.click(origin.line, origin.column);
+            self.client.highlight_find(false);
+            self.find_status = None;
+        }
+    }
+
+    pub fn clear_search_origin(&mut self) {
+        self.search_origin = None;
+    }
+
+    pub fn save(&mut self) -> Option<ClientResult<()>> {
+        let file = self.file()?.to_owned();
+        Some(self.client.save(&file))
+    }
+
+    pub fn file(&self) -> Option<&str> {
+        match self.file {
+            Some(ref file) if !file.is_empty() => Some(file),
+            _ => None,
+        }
+    }
+
+    fn update_window(&mut self, window: &mut Window) {
+        let cursor = window.get_cursor();
+        if cursor.line < self.cache.before() {
             error!(
                 "cursor is on line {} but there are {} invalid lines in cache.",
-                self.cursor.line,
+                cursor.line,
                 self.cache.before()
             );
             return;
         }
-        let cursor_line = self.cursor.line - self.cache.before();
+        let cursor_line = cursor.line - self.cache.before();
         let nb_lines = self.cache.lines().len() as u64;
-        self.gutter_size = 1
-            + (self.cache.before() + nb_lines + self.cache.after())
-                .to_string()
-                .len() as u16;
-        self.window.update(cursor_line, nb_lines);
-    }
-
-    fn get_click_location(&self, x: u64, y: u64) -> (u64, u64) {
-        let lineno = x + self.cache.before() + self.window.start();
-        if let Some(line) = self.cache.lines().get(x as usize) {
-            if y == 0 {
-                return (lineno, 0);
+        self.gutter_size = match self.line_numbers {
+            LineNumbers::Off => 0,
+            // Room for the largest number and a separating space.
+            _ => 1 + self.line_count().to_string().len() as u16,
+        };
+        let width = self.text_width(window);
+        let start = window.start();
+        let height = self.row_height(window);
+        window.update(cursor_line, nb_lines, self.scrolloff, height);
+        if window.start() != start {
+            self.request_lines(window);
+        }
+
+        match self.cursor_column(window) {
+            Some(column) if self.wrap.is_none() => {
+                window.follow_column(column, width, self.sidescroll, self.sidescrolloff)
             }
-            let mut text_len: u16 = 0;
-            for (idx, c) in line.text.chars().enumerate() {
-                text_len = self.translate_char_width(text_len, c);
-                if u64::from(text_len) >= y {
-                    return (lineno as u64, idx as u64 + 1);
+            _ => window.set_left(0),
+        }
+    }
+
+    fn row_height<'a>(&'a self, window: &Window) -> impl Fn(u64) -> u64 + 'a {
+        let width = self.text_width(window);
+        move |idx| match self.cache.lines().get(idx as usize) {
+            Some(line) => LineLayout::new(&line.text, width, self.tab_width, self.wrap.as_ref())
+                .height() as u64,
+            None => 1,
+        }
+    }
+
+    /// Moves the window over the text, and the cursor if it would be
+    /// scrolled off screen or the command moves it along.
+    pub fn scroll(&mut self, window: &mut Window, scroll: Scroll) {
+        let before = self.cache.before();
+        let cursor = window.get_cursor();
+        if cursor.line < before {
+            return;
+        }
+        // Lines relative to the cache, like the window start.
+        let line = cursor.line - before;
+        let last = self.line_count().saturating_sub(before + 1);
+        let size = u64::from(window.size());
+        let offset = cmp::min(u64::from(self.scrolloff), size.saturating_sub(1) / 2);
+        let clamp = |line: i64| cmp::max(0, cmp::min(line, last as i64)) as u64;
+        let start = window.start() as i64;
+        let target = {
+            let height = self.row_height(window);
+            match scroll {
+                Scroll::CursorTop => {
+                    window.show_rows_above(line, offset, &height);
+                    line
+                }
+                Scroll::CursorCenter => {
+                    let rows = size.saturating_sub(height(line)) / 2;
+                    window.show_rows_above(line, rows, &height);
+                    line
+                }
+                Scroll::CursorBottom => {
+                    let rows = size.saturating_sub(height(line) + offset);
+                    window.show_rows_above(line, rows, &height);
+                    line
+                }
+                Scroll::Lines(count) => {
+                    let start = clamp(start + count);
+                    window.set_start(start);
+                    let bottom = (start + size).saturating_sub(offset + 1);
+                    cmp::min(last, cmp::max(cmp::min(line, bottom), start + offset))
+                }
+                Scroll::HalfPages(count) => {
+                    let lines = count * cmp::max(1, size as i64 / 2);
+                    window.set_start(clamp(start + lines));
+                    clamp(line as i64 + lines)
+                }
+                Scroll::Pages(count) => {
+                    // Keep two lines of context.
+                    let lines = count * cmp::max(1, size as i64 - 2);
+                    let start = clamp(start + lines);
+                    window.set_start(start);
+                    if count > 0 {
+                        cmp::min(last, cmp::max(line, start + offset))
+                    } else {
+                        cmp::min(line, (start + size).saturating_sub(offset + 1))
+                    }
                 }
             }
-            return (lineno, line.text.len() as u64 + 1);
+        };
+        if target != line {
+            let line = before + target;
+            let column = self.first_non_blank(line);
+            self.move_cursor(window, Cursor { line, column });
+        }
+        self.request_lines(window);
+    }
+
+    /// Moves the cursor and shows it there before xi-core confirms it, so
+    /// that the window does not scroll back.
+    fn move_cursor(&mut self, window: &mut Window, cursor: Cursor) {
+        self.goto(cursor.line, cursor.column);
+        window.set_cursor(&cursor);
+        self.cursor = cursor;
+    }
+
+    fn cursor_column(&self, window: &Window) -> Option<u16> {
+        let cursor = window.get_cursor();
+        let line = self.cached_line(cursor.line)?;
+        Some(self.layout(line, window).position(cursor.column as usize).1)
+    }
+
+    pub fn scroll_horizontal(&mut self, window: &mut Window, columns: i64) {
+        if self.wrap.is_some() {
+            return;
+        }
+        let left = cmp::max(0, i64::from(window.left()) + columns);
+        window.set_left(cmp::min(left, i64::from(u16::MAX)) as u16);
+        self.keep_cursor_in_view(window);
+    }
+
+    pub fn scroll_cursor_to_edge(&mut self, window: &mut Window, left_edge: bool) {
+        let column = match self.cursor_column(window) {
+            Some(column) if self.wrap.is_none() => column,
+            _ => return,
+        };
+        let width = self.text_width(window);
+        let offset = cmp::min(self.sidescrolloff, width.saturating_sub(1) / 2);
+        let left = if left_edge {
+            column.saturating_sub(offset)
         } else {
-            warn!("no line at index {} found in cache", x);
-            return (x, y);
+            (column + offset + 1).saturating_sub(width)
+        };
+        window.set_left(left);
+    }
+
+    fn keep_cursor_in_view(&mut self, window: &mut Window) {
+        let cursor = window.get_cursor();
+        let layout = match self.cached_line(cursor.line) {
+            Some(line) => self.layout(line, window),
+            None => return,
+        };
+        let width = self.text_width(window);
+        let offset = cmp::min(self.sidescrolloff, width.saturating_sub(1) / 2);
+        let first = window.left() + offset;
+        let last = (window.left() + width).saturating_sub(offset + 1);
+        let column = layout.position(cursor.column as usize).1;
+        let target = if column < first {
+            first
+        } else if column > last {
+            last
+        } else {
+            return;
+        };
+        let idx = layout.offset_at(0, target) as u64;
+        if idx != cursor.column {
+            let cursor = Cursor {
+                line: cursor.line,
+                column: idx,
+            };
+            self.move_cursor(window, cursor);
         }
     }
 
-    fn click(&mut self, x: u64, y: u64) {
-        let (line, column) = self.get_click_location(x, y);
+    fn get_click_location(&self, window: &Window, x: u64, y: u64) -> (u64, u64) {
+        // Clicks on the gutter go to the start of the line.
+        let y = y.saturating_sub(u64::from(self.gutter_size)) as u16;
+        let mut row = x as usize;
+        let mut idx = window.start();
+        while let Some(line) = self.cache.lines().get(idx as usize) {
+            let layout = self.layout(line, window);
+            if row < layout.height() {
+                let column = layout.offset_at(row, y + window.left());
+                return (self.cache.before() + idx, column as u64);
+            }
+            row -= layout.height();
+            idx += 1;
+        }
+        warn!("no line at row {} found in cache", x);
+        (self.cache.before() + idx, 0)
+    }
+
+    fn click(&mut self, window: &Window, x: u64, y: u64) {
+        let (line, column) = self.get_click_location(window, x, y);
         self.client.click(line, column);
     }
 
-    fn drag(&mut self, x: u64, y: u64) {
-        let (line, column) = self.get_click_location(x, y);
+    fn drag(&mut self, window: &Window, x: u64, y: u64) {
+        let (line, column) = self.get_click_location(window, x, y);
         self.client.drag(line, column);
     }
 
-    pub fn handle_input(&mut self, event: Event) {
+    pub fn handle_input(&mut self, event: Event, window: &Window) {
+        // Mouse positions are relative to the screen, starting at 1.
+        let row = |x: u16| u64::from(x.saturating_sub(window.y() + 1));
+        let column = |y: u16| u64::from(y.saturating_sub(window.x() + 1));
         match event {
             Event::Key(key) => match key {
                 Key::Char(c) => self.client.insert(c),
@@ -222,263 +1066,333 @@ impl View {
             },
             Event::Mouse(mouse_event) => match mouse_event {
                 MouseEvent::Press(press_event, y, x) => match press_event {
-                    MouseButton::Left => self.click(u64::from(x) - 1, u64::from(y) - 1),
+                    MouseButton::Left => self.click(window, row(x), column(y)),
                     MouseButton::WheelUp => self.client.up(),
                     MouseButton::WheelDown => self.client.down(),
                     button => error!("unhandled button {:?}", button),
                 },
                 MouseEvent::Release(..) => {}
-                MouseEvent::Hold(y, x) => self.drag(u64::from(x) - 1, u64::from(y) - 1),
+                MouseEvent::Hold(y, x) => self.drag(window, row(x), column(y)),
             },
             ev => error!("unhandled event {:?}", ev),
         }
     }
 
-    pub fn render_error<W: Write>(&mut self, w: &mut W, msg: &str) {
-        let win_size = self.window.size() + 1;
-        write!(
-            w,
-            "{}{}{}error{}{} : {}",
-            Goto(1, win_size),
-            CurrentLine,
-            Bold,
-            color::Fg(color::Red),
-            Reset,
-            msg
-        )
-        .unwrap();
+    pub fn render_error(&mut self, screen: &mut Screen, window: &Window, msg: &str) {
+        let text = fit_width(&format!("error : {}", msg), window.width());
+        let label_len = cmp::min("error".len(), text.len());
+        let (x, y) = (window.x(), window.y() + window.size());
+        let label = CellStyle::fg(Color::Red).bold();
+        let x = x + screen.print(x, y, &text[..label_len], label);
+        screen.print(x, y, &text[label_len..], CellStyle::default());
     }
 
-    fn render_lines<W: Write>(&self, w: &mut W, styles: &HashMap<u64, Style>) {
+    fn render_lines(&self, screen: &mut Screen, window: &Window, styles: &HashMap<u64, Style>) {
         debug!("rendering lines");
         trace!("current cache\n{:?}", self.cache);
 
-        let lines = self
-            .cache
-            .lines()
-            .iter()
-            .skip(self.window.start() as usize)
-            .take(self.window.size() as usize);
-
-        let mut line_strings = String::new();
-        let mut line_no = self.cache.before() + self.window.start();
-        for (line_index, line) in lines.enumerate() {
-            line_strings.push_str(&self.render_line_str(line, Some(line_no), line_index, styles));
-            line_no += 1;
-        }
-
-        let line_count = self.cache.lines().len() as u16;
-        let win_size = self.window.size();
-        if win_size > line_count {
-            for num in line_count..win_size {
-                line_strings.push_str(&self.render_line_str(
-                    &Line::default(),
-                    None,
-                    num as usize,
-                    styles,
-                ));
+        let lines = self.cache.lines().iter().skip(window.start() as usize);
+
+        let first_line = self.cache.before() + window.start();
+        let mut row_index = 0;
+        for (line_no, line) in (first_line..).zip(lines) {
+            let layout = self.layout(line, window);
+            for row in 0..layout.height() {
+                if row_index >= window.size() {
+                    break;
+                }
+                let y = window.y() + row_index;
+                // Only the first row of a line gets its number.
+                let lineno = if row == 0 { Some(line_no) } else { None };
+                self.render_gutter(screen, window, y, lineno);
+                self.render_row(screen, window, y, Some((line, &layout, row)), styles);
+                row_index += 1;
             }
         }
-        w.write_all(line_strings.as_bytes()).unwrap();
+
+        for row_index in row_index..window.size() {
+            let y = window.y() + row_index;
+            self.render_gutter(screen, window, y, None);
+            self.render_row(screen, window, y, None, styles);
+        }
     }
 
-    fn render_status<W: Write>(&mut self, w: &mut W, state: &str) {
-        let win_size = self.window.size() + 1;
-        let file = match self.file.as_ref().map(|s| s) {
+    fn render_status(&mut self, screen: &mut Screen, window: &Window, state: &str) {
+        let file = match self.file.as_ref() {
             None => "<nofile>".to_owned(),
             Some(file) => file.to_owned(),
         };
-        let cur = self.window.get_cursor();
-        write!(
-            w,
-            "{}{}{}{}{}{} : '{}' {} / {}",
-            Goto(1, win_size),
-            CurrentLine,
-            Bold,
-            color::Fg(color::Green),
-            state,
-            Reset,
-            file,
-            cur.line + 1,
-            cur.column + 1
-        )
-        .unwrap();
+        let cur = window.get_cursor();
+        let matches = match self.search_matches() {
+            Some((current, total)) => format!(" [{}/{}]", current, total),
+            None => String::new(),
+        };
+        let modified = if !self.is_modified() { "" } else { " [+]" };
+        let message = match self.message {
+            Some(ref message) => format!("  {}", message),
+            None => String::new(),
+        };
+        let status = fit_width(
+            &format!(
+                "{} : '{}'{} {} / {}{}{}",
+                state,
+                file,
+                modified,
+                cur.line + 1,
+                cur.column + 1,
+                matches,
+                message
+            ),
+            window.width(),
+        );
+        let state_len = cmp::min(state.len(), status.len());
+        let (x, y) = (window.x(), window.y() + window.size());
+        let label = CellStyle::fg(Color::Green).bold();
+        let x = x + screen.print(x, y, &status[..state_len], label);
+        screen.print(x, y, &status[state_len..], CellStyle::default());
     }
 
-    fn render_line_str(
+    fn render_row(
         &self,
-        line: &Line,
-        lineno: Option<u64>,
-        line_index: usize,
+        screen: &mut Screen,
+        window: &Window,
+        y: u16,
+        line: Option<(&Line, &LineLayout, usize)>,
         styles: &HashMap<u64, Style>,
-    ) -> String {
-        let text = self.escape_control_and_add_styles(styles, line);
-        if let Some(line_no) = lineno {
-            format!(
-                "{}{}{}{}{}",
-                Goto(1, line_index as u16 + 1),
-                CurrentLine,
-                (line_no + 1).to_string(),
-                Goto(self.gutter_size + 1, line_index as u16 + 1),
-                &text
-            )
+    ) {
+        let x = window.x() + self.gutter_size;
+        let width = self.text_width(window);
+        screen.clear(x, y, width, CellStyle::default());
+        let (line, layout, row) = match line {
+            Some(line) => line,
+            None if width > 0 => {
+                screen.print(x, y, "~", CellStyle::default());
+                return;
+            }
+            None => return,
+        };
+        let indent = layout.indent(row);
+        if indent > 0 {
+            let showbreak: String = self.wrap.as_ref().map_or(String::new(), |wrap| {
+                wrap.showbreak.chars().take(indent as usize).collect()
+            });
+            screen.print(x, y, &showbreak, CellStyle::default());
+        }
+        self.render_text(
+            screen,
+            (x + indent, y),
+            styles,
+            line,
+            (layout, row),
+            (window.left(), width - indent),
+        );
+    }
+
+    fn render_gutter(&self, screen: &mut Screen, window: &Window, y: u16, lineno: Option<u64>) {
+        let width = self.gutter_size.saturating_sub(1) as usize;
+        let cursor = window.get_cursor().line;
+        screen.clear(window.x(), y, self.gutter_size, CellStyle::default());
+        let line_no = match lineno {
+            Some(line_no) if width > 0 => line_no,
+            _ => return,
+        };
+        let distance = cmp::max(line_no, cursor) - cmp::min(line_no, cursor);
+        let number = match self.line_numbers {
+            LineNumbers::Relative => distance,
+            LineNumbers::Hybrid if line_no != cursor => distance,
+            _ => line_no + 1,
+        };
+        let style = if line_no == cursor {
+            CellStyle::fg(Color::Yellow).bold()
         } else {
-            format!(
-                "{}{}~{}",
-                Goto(self.gutter_size + 1, line_index as u16 + 1),
-                CurrentLine,
-                &text
-            )
-        }
-    }
-
-    fn escape_control_and_add_styles(&self, styles: &HashMap<u64, Style>, line: &Line) -> String {
-        let mut position: u16 = 0;
-        let mut text = String::with_capacity(line.text.capacity());
-        for c in line.text.chars() {
-            match c {
-                '\x00'...'\x08' | '\x0a'...'\x1f' | '\x7f' => {
-                    // Render in caret notation, i.e. '\x02' is rendered as '^B'
-                    text.push('^');
-                    text.push((c as u8 ^ 0x40u8) as char);
-                    position += 2;
-                }
-                '\t' => {
-                    let tab_width = self.tab_width_at_position(position);
-                    text.push_str(&" ".repeat(tab_width as usize));
-                    position += tab_width;
+            CellStyle::default()
+        };
+        let number = format!("{:>width$}", number, width = width);
+        screen.print(window.x(), y, &number, style);
+    }
+
+    /// Draws `row` of the line text at `(x, y)` with control characters
+    /// escaped, tabs expanded and styles applied, the `width` columns from
+    /// column `left` of the row. Text cut off is indicated by `<` and `>`.
+    fn render_text(
+        &self,
+        screen: &mut Screen,
+        (x, y): (u16, u16),
+        styles: &HashMap<u64, Style>,
+        line: &Line,
+        (layout, row): (&LineLayout, usize),
+        (left, width): (u16, u16),
+    ) {
+        let range = layout.row(row);
+        let row_width = layout.row_width(row);
+        let indicators = width >= 2;
+        let precedes = indicators && left > 0 && row_width > 0;
+        let extends = indicators && row_width > left + width;
+        // The columns of the row that are shown, around the indicators.
+        let first = if precedes { left + 1 } else { left };
+        let last = if extends {
+            left + width - 1
+        } else {
+            left + width
+        };
+        let spans = self.style_spans(styles, line);
+        let mut column: u16 = 0;
+        let graphemes = line.text.grapheme_indices(true).enumerate();
+        for (idx, (byte_idx, grapheme)) in graphemes.skip(range.start).take(range.len()) {
+            let char_width = layout.width(idx);
+            let (start, end) = (column, column + char_width);
+            column = end;
+            // Wide characters partly cut off are left blank.
+            if start < first {
+                continue;
+            }
+            if end > last {
+                break;
+            }
+            let style = spans
+                .iter()
+                .filter(|(range, _)| range.contains(&byte_idx))
+                .fold(CellStyle::default(), |style, (_, other)| {
+                    style.merge(*other)
+                });
+            let at = x + start - left;
+            match wrap::control_char(grapheme) {
+                Some(c) => {
+                    screen.print(at, y, &wrap::caret_notation(c), style);
                 }
-                _ => {
-                    text.push(c);
-                    position += 1;
+                None if grapheme == "\t" => screen.clear(at, y, char_width, style),
+                None => {
+                    screen.print(at, y, grapheme, style);
                 }
             }
         }
-        if line.styles.is_empty() {
-            return text;
+        if precedes {
+            screen.print(x, y, "<", CellStyle::default());
         }
-        let mut style_sequences = self.get_style_sequences(styles, line);
-        for style in style_sequences.drain(..) {
-            trace!("inserting style: {:?}", style);
-            if style.0 >= text.len() {
-                text.push_str(&style.1);
-            } else {
-                text.insert_str(style.0, &style.1);
-            }
+        if extends {
+            screen.print(x + width - 1, y, ">", CellStyle::default());
         }
-        trace!("styled line: {:?}", text);
-        text
     }
 
-    fn tab_width_at_position(&self, position: u16) -> u16 {
-        self.tab_width - (position % self.tab_width)
-    }
-
-    fn get_style_sequences(
+    fn style_spans(
         &self,
         styles: &HashMap<u64, Style>,
         line: &Line,
-    ) -> Vec<(usize, String)> {
-        let mut style_sequences: Vec<(usize, String)> = Vec::new();
-        let mut prev_style_end: usize = 0;
-        for style_def in &line.styles {
-            let start_idx = if style_def.offset >= 0 {
-                (prev_style_end + style_def.offset as usize)
-            } else {
-                (prev_style_end - ((-style_def.offset) as usize))
-            };
-            let end_idx = start_idx + style_def.length as usize;
-            prev_style_end = end_idx;
-
-            if let Some(style) = styles.get(&style_def.style_id) {
-                let start_sequence = match set_style(style) {
-                    Ok(s) => s,
-                    Err(e) => {
-                        error!("could not get CSI sequence to set style {:?}: {}", style, e);
-                        continue;
-                    }
-                };
-                let end_sequence = match reset_style(style) {
-                    Ok(s) => s,
-                    Err(e) => {
-                        error!(
-                            "could not get CSI sequence to reset style {:?}: {}",
-                            style, e
-                        );
-                        continue;
-                    }
-                };
-                style_sequences.push((start_idx, start_sequence));
-                style_sequences.push((end_idx, end_sequence));
+    ) -> Vec<(Range<usize>, CellStyle)> {
+        let mut spans = Vec::new();
+        for (range, style_id) in style_ranges(line) {
+            if let Some(style) = styles.get(&style_id) {
+                spans.push((range, CellStyle::from_style(style)));
             } else {
-                error!(
-                    "no style ID {} found not applying style.",
-                    style_def.style_id
-                );
+                error!("no style ID {} found not applying style.", style_id);
             };
         }
-
-        style_sequences.sort_by(|a, b| a.0.cmp(&b.0));
-        style_sequences.reverse();
-        trace!("{:?}", style_sequences);
-        style_sequences
+        trace!("{:?}", spans);
+        spans
     }
 
-    fn render_cursor<W: Write>(&self, w: &mut W) {
+    fn render_cursor(&self, screen: &mut Screen, window: &Window) {
         debug!("rendering cursor");
         if self.cache.is_empty() {
             debug!("cache is empty, rendering cursor at the top left corner");
-            if let Err(e) = write!(w, "{}", Goto(1, 1)) {
-                error!("failed to render cursor: {}", e);
-            }
+            screen.set_cursor(window.x(), window.y());
             return;
         }
 
-        if self.cursor.line < self.cache.before() {
+        let cursor = window.get_cursor();
+        if cursor.line < self.cache.before() {
             error!(
                 "The cursor is on line {} which is marked invalid in the cache",
-                self.cursor.line
+                cursor.line
             );
             return;
         }
-        let line_idx = self.cursor.line - self.cache.before();
+        let line_idx = cursor.line - self.cache.before();
         let line = match self.cache.lines().get(line_idx as usize) {
             Some(line) => line,
             None => {
-                error!("no valid line at cursor index {}", self.cursor.line);
+                error!("no valid line at cursor index {}", cursor.line);
                 return;
             }
         };
 
-        if line_idx < self.window.start() {
+        if line_idx < window.start() {
             error!(
                 "the line that has the cursor (nb={}, cache_idx={}) not within the displayed window ({:?})",
-                self.cursor.line,
+                cursor.line,
                 line_idx,
-                self.window
+                window
             );
             return;
         }
-        let line_pos = line_idx - self.window.start();
-
-        let column = line
-            .text
-            .chars()
-            .take(self.cursor.column as usize)
-            .fold(0, |acc, c| acc + self.translate_char_width(acc, c));
-
-        let cursor_pos = Goto(self.gutter_size + column as u16 + 1, line_pos as u16 + 1);
-        if let Err(e) = write!(w, "{}", cursor_pos) {
-            error!("failed to render cursor: {}", e);
+        // Rows taken by the lines above, and by the line itself before the
+        // cursor.
+        let rows_above: usize = self
+            .cache
+            .lines()
+            .iter()
+            .skip(window.start() as usize)
+            .take((line_idx - window.start()) as usize)
+            .map(|line| self.layout(line, window).height())
+            .sum();
+        let (row, column) = self.layout(line, window).position(cursor.column as usize);
+        let column = column.saturating_sub(window.left());
+        let line_pos = rows_above + row;
+        if line_pos >= window.size() as usize {
+            error!(
+                "the cursor row {} is below the window ({:?})",
+                line_pos, window
+            );
+            return;
         }
+
+        screen.set_cursor(
+            window.x() + self.gutter_size + column,
+            window.y() + line_pos as u16,
+        );
         debug!("cursor rendered at ({}, {})", line_pos, column);
     }
+}
+
+fn undo_limit_message(direction: i64) -> String {
+    if direction < 0 {
+        "Already at oldest change".to_owned()
+    } else {
+        "Already at newest change".to_owned()
+    }
+}
+
+fn describe_undo_state(position: &str, seq: usize, state: &UndoState) -> String {
+    let seconds = swap::now().saturating_sub(state.time);
+    format!("{} #{}  {} seconds ago", position, seq, seconds)
+}
+
+/// Returns the byte ranges of the styles of a line, with their ids.
+fn style_ranges(line: &Line) -> impl Iterator<Item = (Range<usize>, u64)> + '_ {
+    let mut prev_style_end: usize = 0;
+    line.styles.iter().map(move |style_def| {
+        let start_idx = if style_def.offset >= 0 {
+            prev_style_end + style_def.offset as usize
+        } else {
+            prev_style_end - ((-style_def.offset) as usize)
+        };
+        let end_idx = start_idx + style_def.length as usize;
+        prev_style_end = end_idx;
+        (start_idx..end_idx, style_def.style_id)
+    })
+}
 
-    fn translate_char_width(&self, position: u16, c: char) -> u16 {
-        match c {
-            '\x00'...'\x08' | '\x0a'...'\x1f' | '\x7f' => 2,
-            '\t' => self.tab_width_at_position(position),
-            _ => 1,
+pub fn fit_width(text: &str, width: u16) -> String {
+    let mut fitted = String::new();
+    let mut used = 0;
+    for grapheme in text.graphemes(true) {
+        let grapheme_width = wrap::grapheme_width(grapheme, used, wrap::TAB_WIDTH);
+        if used + grapheme_width > width {
+            break;
         }
+        fitted.push_str(grapheme);
+        used += grapheme_width;
     }
+    fitted.push_str(&" ".repeat((width - used) as usize));
+    fitted
 }
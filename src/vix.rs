@@ -1,22 +1,37 @@
+use crate::backend::{Backend, TtyEvent};
 use crate::command_prompt::{Command, CommandPrompt};
 use crate::editor::Editor;
-use crate::tty::{Tty, TtyEvent};
+use crate::layout::{Direction, SplitDirection};
+use crate::screen::Screen;
+use crate::search::SearchDirection;
+use crate::swap::Journal;
+use crate::tty::Tty;
+use crate::window::Scroll;
 use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
 use futures::{future, Async, Future, Poll, Sink, Stream};
-use std::io::{self, Write};
+use std::io;
+use std::mem;
+use std::time::{Duration, Instant};
 use termion::event::{Event, Key};
-use tokio;
+use tokio::timer::Interval;
 use xrl::{
-    AvailablePlugins, Client, ConfigChanged, Frontend, FrontendBuilder, MeasureWidth,
-    ModifySelection, PluginStarted, PluginStoped, ScrollTo, ServerResult, Style, ThemeChanged,
-    Update, UpdateCmds, XiNotification,
+    Alert, AvailablePlugins, Client, ConfigChanged, FindStatus, Frontend, FrontendBuilder,
+    MeasureWidth, PluginStarted, PluginStoped, ScrollTo, Style, ThemeChanged, Update, UpdateCmds,
+    ViewId, XiNotification,
 };
 
+/// Seconds between checks for files changed on disk.
+const CHECKTIME_INTERVAL: u64 = 1;
+/// Seconds between writes of the recovery journals.
+const JOURNAL_INTERVAL: u64 = 4;
+
 #[derive(Debug)]
 pub enum CoreEvent {
     Update(Update),
     ScrollTo(ScrollTo),
     SetStyle(Style),
+    FindStatus(FindStatus),
+    Alert(Alert),
 }
 
 #[derive(Debug, PartialEq)]
@@ -27,70 +42,197 @@ pub enum Mode {
     Search,
     Insert,
     Visual(bool),
+    Quickfix,
+    /// A file was changed on disk, asking whether to reload it.
+    Conflict(ViewId, String),
+    /// A crashed vix left a recovery journal for an opened file.
+    Recover(ViewId, Journal),
 }
 
-pub struct Vix {
+pub struct Vix<B: Backend = Tty> {
     editor: Editor,
     mode: Mode,
     prompt: Option<CommandPrompt>,
-    tty: Tty,
-    tty_size: (u16, u16),
+    backend: B,
+    screen: Screen,
     shutdown: bool,
-    last_event: Event,
+    pending_key: Option<Key>,
+    /// Set while `autoread` needs files to be checked periodically.
+    checktime: Option<Interval>,
+    /// Set while a buffer has changes for its recovery journal, or a journal
+    /// to remove.
+    journal: Option<Interval>,
+    /// Whether the screen needs to be rendered again.
+    dirty: bool,
 }
 
 impl Vix {
     pub fn new(client: Client, events: UnboundedReceiver<CoreEvent>) -> Result<Self, io::Error> {
-        let mut dir = dirs::config_dir().unwrap();
-        dir.push("xi");
-        tokio::run(client.client_started(dir.to_str(), None).map_err(|_| ()));
+        let dir = dirs::config_dir().map(|dir| dir.join("xi"));
+        let config_dir = dir.as_ref().and_then(|dir| dir.to_str());
+        tokio::run(client.client_started(config_dir, None).map_err(|_| ()));
+        Ok(Vix::with_backend(client, events, Tty::new()?))
+    }
+}
 
-        Ok(Vix {
+impl<B: Backend> Vix<B> {
+    /// Creates vix drawing on `backend`, for a core that was already told
+    /// that the client started.
+    pub fn with_backend(client: Client, events: UnboundedReceiver<CoreEvent>, backend: B) -> Self {
+        Vix {
             editor: Editor::new(client, events),
             mode: Mode::Vix,
             prompt: None,
-            tty: Tty::new()?,
-            tty_size: (0, 0),
+            backend,
+            screen: Screen::new(0, 0),
             shutdown: false,
-            last_event: Event::Unsupported(Vec::new()),
-        })
+            pending_key: None,
+            checktime: None,
+            journal: None,
+            dirty: true,
+        }
     }
 
     fn handle_resize(&mut self, size: (u16, u16)) {
-        self.tty_size = size;
+        self.screen.resize(size.0, size.1);
         self.editor.handle_resize(size);
     }
 
+    fn check_files(&mut self) {
+        if let Some((view_id, warning)) = self.editor.check_files() {
+            self.dirty = true;
+            self.prompt = None;
+            self.mode = Mode::Conflict(view_id, warning);
+        }
+    }
+
     fn exit(&mut self) {
         self.shutdown = true;
     }
 
+    fn quit(&mut self, force: bool) {
+        if !self.editor.close_pane() {
+            self.quit_all(force);
+        }
+    }
+
+    fn suspend(&mut self) {
+        info!("suspending");
+        self.editor.write_journals();
+        if let Err(err) = self.backend.suspend() {
+            error!("failed to suspend: {}", err);
+        }
+        // Files may have been changed from the shell.
+        self.check_files();
+    }
+
+    /// Exits, unless a buffer has unsaved changes and `force` is not set.
+    fn quit_all(&mut self, force: bool) {
+        if !force {
+            if let Some(name) = self.editor.modified_buffer() {
+                self.mode = Mode::Error(format!(
+                    "No write since last change for buffer \"{}\" (add ! to override)",
+                    name
+                ));
+                return;
+            }
+        }
+        info!("exiting ...");
+        self.editor.remove_journals();
+        self.editor.store_file_marks();
+        self.exit();
+    }
+
     pub fn handle_cmd(&mut self, cmd: Command) {
         self.mode = Mode::Vix;
         match cmd {
-            Command::Quit => {
-                info!("exiting ...");
-                self.exit();
+            Command::Quit(force) => self.quit(force),
+            Command::QuitAll(force) => self.quit_all(force),
+            Command::Split(direction, file) => self.editor.split(direction, file),
+            Command::CloseWindow => {
+                if !self.editor.close_pane() {
+                    self.mode = Mode::Error("Cannot close last window".into());
+                }
             }
+            Command::OnlyWindow => self.editor.only_pane(),
+            Command::CheckTime => self.check_files(),
+            Command::UndoStep(count) => self.editor.undo_step(count),
+            Command::UndoTime(seconds) => self.editor.undo_travel(seconds),
+            Command::TabNew(file) => self.editor.new_tab(file),
+            Command::TabNext => self.editor.next_tab(true),
+            Command::TabPrev => self.editor.next_tab(false),
+            Command::TabClose => {
+                if !self.editor.close_tab() {
+                    self.mode = Mode::Error("Cannot close last tab page".into());
+                }
+            }
+            Command::TabOnly => self.editor.only_tab(),
             Command::Cancel => {
+                self.editor.restore_search_origin();
                 self.prompt = None;
             }
             Command::Save(view, exit) => {
                 self.editor.save(view);
                 if exit {
-                    self.exit();
+                    self.quit(false);
+                }
+            }
+            Command::SaveAll(exit) => {
+                self.editor.save_all();
+                if exit {
+                    self.quit_all(false);
+                }
+            }
+            Command::SaveModified(exit) => {
+                self.editor.save_modified();
+                if exit {
+                    self.quit(false);
                 }
             }
             Command::Open(file) => {
+                self.editor.push_jump();
                 self.editor.open(file);
             }
             Command::SetTheme(theme) => {
                 self.editor.set_theme(&theme);
             }
-            Command::Search(search) => {
-                self.editor.find(&search, true, false, false);
-                self.editor.find_all();
-                self.editor.highlight_find(true);
+            Command::Search(search, direction) => {
+                self.editor.search(&search, direction);
+                self.editor.clear_search_origin();
+            }
+            Command::VimGrep {
+                pattern,
+                files,
+                all,
+                jump,
+            } => self.editor.vimgrep(&pattern, &files, all, jump),
+            Command::Grep(args) => self.editor.grep(&args),
+            Command::QuickfixOpen => match self.editor.check_quickfix() {
+                Ok(()) => self.mode = Mode::Quickfix,
+                Err(err) => self.mode = Mode::Error(err.to_string()),
+            },
+            Command::QuickfixNext => {
+                if let Err(err) = self.editor.quickfix_next() {
+                    self.mode = Mode::Error(err.to_string());
+                }
+            }
+            Command::QuickfixPrev => {
+                if let Err(err) = self.editor.quickfix_prev() {
+                    self.mode = Mode::Error(err.to_string());
+                }
+            }
+            Command::QuickfixGoto(nr) => {
+                if let Err(err) = self.editor.quickfix_goto(nr) {
+                    self.mode = Mode::Error(err.to_string());
+                }
+            }
+            Command::Set(settings) => {
+                for setting in settings {
+                    if let Err(err) = self.editor.options.set(&setting) {
+                        error!("failed to set option '{}': {}", setting, err);
+                        self.mode = Mode::Error(err.to_string());
+                    }
+                }
             }
             Command::GotoLine(line) => {
                 let line = match line {
@@ -104,10 +246,21 @@ impl Vix {
 
     fn handle_input(&mut self, event: Event) {
         debug!("event: {:?}@{:?}", event, self.mode);
+        self.editor.clear_message();
+        // A command ends the change made by the previous one, typing in
+        // insert mode is one change until the next command.
+        if self.mode != Mode::Insert {
+            self.editor.checkpoint();
+        }
         match event.clone() {
-            Event::Key(Key::Ctrl('c')) => self.exit(),
+            Event::Key(Key::Ctrl('c')) => self.quit_all(false),
             Event::Key(Key::Esc) => {
                 info!("entering vix mode");
+                if self.mode == Mode::Search {
+                    self.editor.restore_search_origin();
+                }
+                self.prompt = None;
+                self.pending_key = None;
                 self.mode = Mode::Vix;
                 self.editor.collapse_selections();
             }
@@ -129,15 +282,11 @@ impl Vix {
                         self.editor.cut();
                         self.mode = Mode::Vix
                     }
-                    Key::Left => {
-                        if !*line_mode {
-                            self.editor.select_left();
-                        }
+                    Key::Left if !*line_mode => {
+                        self.editor.select_left();
                     }
-                    Key::Right => {
-                        if !*line_mode {
-                            self.editor.select_right();
-                        }
+                    Key::Right if !*line_mode => {
+                        self.editor.select_right();
                     }
                     Key::Down => {
                         self.editor.select_down();
@@ -180,6 +329,44 @@ impl Vix {
                     Key::Char('l') => self.editor.select_right(),
                     _ => {}
                 },
+                Mode::Recover(..) => {
+                    if let Mode::Recover(view_id, journal) = mem::replace(&mut self.mode, Mode::Vix)
+                    {
+                        match key {
+                            Key::Char('r') | Key::Char('R') => {
+                                self.editor.recover(view_id, &journal)
+                            }
+                            Key::Char('d') | Key::Char('D') => self.editor.delete_journal(&journal),
+                            _ => {}
+                        }
+                    }
+                }
+                Mode::Conflict(view_id, _) => {
+                    let view_id = *view_id;
+                    match key {
+                        Key::Char('l') | Key::Char('L') => {
+                            self.editor.reload(view_id);
+                            self.mode = Mode::Vix;
+                        }
+                        Key::Char('o') | Key::Char('O') | Key::Char('\n') => self.mode = Mode::Vix,
+                        _ => {}
+                    }
+                }
+                Mode::Quickfix => match key {
+                    Key::Char('j') | Key::Down => self.editor.quickfix_select(true),
+                    Key::Char('k') | Key::Up => self.editor.quickfix_select(false),
+                    Key::Char('\n') => {
+                        self.editor.quickfix_jump();
+                        self.mode = Mode::Vix;
+                    }
+                    Key::Char('q') => self.mode = Mode::Vix,
+                    _ => {}
+                },
+                Mode::Vix if self.pending_key.is_some() => {
+                    if let Some(prefix) = self.pending_key.take() {
+                        self.handle_key_sequence(prefix, key);
+                    }
+                }
                 Mode::Vix => match key {
                     Key::Delete
                     | Key::Left
@@ -205,7 +392,20 @@ impl Vix {
                     Key::Char('/') => {
                         info!("entering search mode");
                         self.mode = Mode::Search;
-                        self.prompt = Some(CommandPrompt::search());
+                        self.prompt = Some(CommandPrompt::search(
+                            SearchDirection::Forward,
+                            self.editor.search_history(),
+                        ));
+                        self.editor.save_search_origin();
+                    }
+                    Key::Char('?') => {
+                        info!("entering backward search mode");
+                        self.mode = Mode::Search;
+                        self.prompt = Some(CommandPrompt::search(
+                            SearchDirection::Backward,
+                            self.editor.search_history(),
+                        ));
+                        self.editor.save_search_origin();
                     }
                     Key::Char('v') => {
                         info!("entering visual mode");
@@ -222,22 +422,34 @@ impl Vix {
                     Key::Char('u') => {
                         self.editor.undo();
                     }
-                    Key::Char('r') => {
+                    Key::Ctrl('r') => {
                         self.editor.redo();
                     }
-                    Key::Char('d') => {
-                        if self.last_event == Event::Key(Key::Char('d')) {
-                            self.editor.delete_line();
-                        }
-                    }
-                    Key::Char('n') => {
-                        self.editor.find_next(true, false, ModifySelection::None);
-                        self.editor.highlight_find(true);
-                    }
-                    Key::Char('N') => {
-                        self.editor.find_prev(true, false, ModifySelection::None);
-                        self.editor.highlight_find(true);
+                    Key::Ctrl('o') => self.editor.jump_back(),
+                    Key::Ctrl('z') => self.suspend(),
+                    Key::Ctrl('e') => self.editor.scroll(Scroll::Lines(1)),
+                    Key::Ctrl('y') => self.editor.scroll(Scroll::Lines(-1)),
+                    Key::Ctrl('d') => self.editor.scroll(Scroll::HalfPages(1)),
+                    Key::Ctrl('u') => self.editor.scroll(Scroll::HalfPages(-1)),
+                    Key::Ctrl('f') => self.editor.scroll(Scroll::Pages(1)),
+                    Key::Ctrl('b') => self.editor.scroll(Scroll::Pages(-1)),
+                    // Ctrl-i is sent as Tab.
+                    Key::Char('\t') => self.editor.jump_forward(),
+                    Key::Char('G') => self.editor.goto_last_line(),
+                    prefix @ Key::Char('d')
+                    | prefix @ Key::Char('g')
+                    | prefix @ Key::Char('r')
+                    | prefix @ Key::Char('z')
+                    | prefix @ Key::Char('m')
+                    | prefix @ Key::Char('`')
+                    | prefix @ Key::Char('\'')
+                    | prefix @ Key::Ctrl('w') => {
+                        self.pending_key = Some(prefix);
                     }
+                    Key::Char('*') => self.editor.search_word(SearchDirection::Forward, true),
+                    Key::Char('#') => self.editor.search_word(SearchDirection::Backward, true),
+                    Key::Char('n') => self.editor.search_next(false),
+                    Key::Char('N') => self.editor.search_next(true),
                     _ => {}
                 },
                 Mode::Command => {
@@ -256,22 +468,94 @@ impl Vix {
                 }
             }
         }
-        self.last_event = event;
+    }
+
+    fn handle_key_sequence(&mut self, prefix: Key, key: Key) {
+        match (prefix, key) {
+            (Key::Char('d'), Key::Char('d')) => self.editor.delete_line(),
+            (Key::Char('g'), Key::Char('*')) => {
+                self.editor.search_word(SearchDirection::Forward, false)
+            }
+            (Key::Char('g'), Key::Char('#')) => {
+                self.editor.search_word(SearchDirection::Backward, false)
+            }
+            (Key::Char('g'), Key::Char('d')) => self.editor.goto_declaration(false),
+            (Key::Char('g'), Key::Char('D')) => self.editor.goto_declaration(true),
+            (Key::Char('g'), Key::Char('g')) => self.editor.goto_line(0),
+            (Key::Char('g'), Key::Char('j')) => self.editor.move_display_line(true),
+            (Key::Char('g'), Key::Char('k')) => self.editor.move_display_line(false),
+            (Key::Char('g'), Key::Char(';')) => self.editor.goto_change(true),
+            (Key::Char('g'), Key::Char(',')) => self.editor.goto_change(false),
+            (Key::Char('m'), Key::Char(mark)) if mark.is_ascii_alphabetic() => {
+                self.editor.set_mark(mark)
+            }
+            (Key::Char('`'), Key::Char(mark)) => self.editor.goto_mark(mark, true),
+            (Key::Char('\''), Key::Char(mark)) => self.editor.goto_mark(mark, false),
+            (Key::Char('g'), Key::Char('-')) => self.editor.undo_step(-1),
+            (Key::Char('g'), Key::Char('+')) => self.editor.undo_step(1),
+            (Key::Char('r'), Key::Char(chr)) if chr != '\n' => self.editor.replace_char(chr),
+            (Key::Char('g'), Key::Char('t')) => self.editor.next_tab(true),
+            (Key::Char('g'), Key::Char('T')) => self.editor.next_tab(false),
+            (Key::Char('z'), Key::Char('h')) | (Key::Char('z'), Key::Left) => {
+                self.editor.scroll_horizontal(-1)
+            }
+            (Key::Char('z'), Key::Char('l')) | (Key::Char('z'), Key::Right) => {
+                self.editor.scroll_horizontal(1)
+            }
+            (Key::Char('z'), Key::Char('t')) => self.editor.scroll(Scroll::CursorTop),
+            (Key::Char('z'), Key::Char('z')) => self.editor.scroll(Scroll::CursorCenter),
+            (Key::Char('z'), Key::Char('b')) => self.editor.scroll(Scroll::CursorBottom),
+            (Key::Char('z'), Key::Char('s')) => self.editor.scroll_cursor_to_edge(true),
+            (Key::Char('z'), Key::Char('e')) => self.editor.scroll_cursor_to_edge(false),
+            (Key::Ctrl('w'), key) => self.handle_window_key(key),
+            (prefix, key) => debug!("unknown key sequence {:?}{:?}", prefix, key),
+        }
+    }
+
+    fn handle_window_key(&mut self, key: Key) {
+        match key {
+            Key::Char('h') | Key::Left | Key::Ctrl('h') => {
+                self.editor.focus_direction(Direction::Left)
+            }
+            Key::Char('j') | Key::Down | Key::Ctrl('j') => {
+                self.editor.focus_direction(Direction::Down)
+            }
+            Key::Char('k') | Key::Up | Key::Ctrl('k') => self.editor.focus_direction(Direction::Up),
+            Key::Char('l') | Key::Right | Key::Ctrl('l') => {
+                self.editor.focus_direction(Direction::Right)
+            }
+            Key::Char('w') | Key::Ctrl('w') => self.editor.focus_next(true),
+            Key::Char('W') => self.editor.focus_next(false),
+            Key::Char('s') | Key::Char('S') | Key::Ctrl('s') => {
+                self.editor.split(SplitDirection::Horizontal, None)
+            }
+            Key::Char('v') | Key::Ctrl('v') => self.editor.split(SplitDirection::Vertical, None),
+            Key::Char('c') => self.handle_cmd(Command::CloseWindow),
+            Key::Char('q') | Key::Ctrl('q') => self.handle_cmd(Command::Quit(false)),
+            Key::Char('o') | Key::Ctrl('o') => self.editor.only_pane(),
+            Key::Char('=') => self.editor.equalize_panes(),
+            Key::Char('+') => self.editor.resize_pane(SplitDirection::Horizontal, 1),
+            Key::Char('-') => self.editor.resize_pane(SplitDirection::Horizontal, -1),
+            Key::Char('>') => self.editor.resize_pane(SplitDirection::Vertical, 1),
+            Key::Char('<') => self.editor.resize_pane(SplitDirection::Vertical, -1),
+            key => debug!("unknown window command {:?}", key),
+        }
     }
 
     fn handle_command_prompt(&mut self, event: &Event) {
-        if self.prompt.is_some() {
-            let mut prompt = self.prompt.take().unwrap();
-            match prompt.handle_input(&event) {
+        if let Some(mut prompt) = self.prompt.take() {
+            match prompt.handle_input(event) {
                 Ok(None) => {
+                    if let Some(direction) = prompt.search_direction() {
+                        self.editor.incremental_find(prompt.text(), direction);
+                    }
                     self.prompt = Some(prompt);
                 }
                 Ok(Some(cmd)) => {
                     self.handle_cmd(cmd);
-                    self.mode = Mode::Vix;
                 }
                 Err(err) => {
-                    self.mode = Mode::Error(format!("Failed to parse cmd: '{:?}'!", err));
+                    self.mode = Mode::Error(err.to_string());
                     error!("failed to parse cmd: {:?}", err);
                 }
             }
@@ -281,21 +565,26 @@ impl Vix {
     fn process_terminal_events(&mut self) {
         let mut new_size: Option<(u16, u16)> = None;
         loop {
-            match self.tty.poll() {
-                Ok(Async::Ready(Some(event))) => match event {
-                    TtyEvent::Resize(size) => {
-                        new_size = Some(size);
+            match self.backend.poll_event() {
+                Ok(Async::Ready(Some(event))) => {
+                    self.dirty = true;
+                    match event {
+                        TtyEvent::Resize(size) => {
+                            new_size = Some(size);
+                        }
+                        TtyEvent::Input(input) => self.handle_input(input),
                     }
-                    TtyEvent::Input(input) => self.handle_input(input),
-                },
+                }
                 Ok(Async::Ready(None)) => {
                     error!("Terminal stream shutdown, exiting ...");
                     self.shutdown = true;
+                    break;
                 }
                 Ok(Async::NotReady) => break,
                 Err(_) => {
-                    error!("");
+                    error!("Error while reading terminal events, exiting ...");
                     self.shutdown = true;
+                    break;
                 }
             }
         }
@@ -306,47 +595,143 @@ impl Vix {
         }
     }
 
+    fn process_checktime(&mut self) {
+        if self.editor.options.autoread != self.checktime.is_some() {
+            self.checktime = if self.editor.options.autoread {
+                let period = Duration::from_secs(CHECKTIME_INTERVAL);
+                Some(Interval::new(Instant::now() + period, period))
+            } else {
+                None
+            };
+        }
+        let mut elapsed = false;
+        if let Some(ref mut checktime) = self.checktime {
+            while let Ok(Async::Ready(Some(_))) = checktime.poll() {
+                elapsed = true;
+            }
+        }
+        // Do not interrupt the user while a prompt is open.
+        let idle = match self.mode {
+            Mode::Vix => self.prompt.is_none(),
+            Mode::Insert => true,
+            _ => false,
+        };
+        if elapsed && idle {
+            self.check_files();
+        }
+
+        if self.editor.needs_journals() != self.journal.is_some() {
+            self.journal = if self.editor.needs_journals() {
+                let period = Duration::from_secs(JOURNAL_INTERVAL);
+                Some(Interval::new(Instant::now() + period, period))
+            } else {
+                None
+            };
+        }
+        let mut elapsed = false;
+        if let Some(ref mut journal) = self.journal {
+            while let Ok(Async::Ready(Some(_))) = journal.poll() {
+                elapsed = true;
+            }
+        }
+        if elapsed {
+            self.editor.write_journals();
+        }
+    }
+
     fn render(&mut self) -> Result<(), io::Error> {
+        let state = match self.mode {
+            Mode::Vix => "vix",
+            Mode::Insert => "insert",
+            Mode::Visual(line_mode) => {
+                if line_mode {
+                    "visual line"
+                } else {
+                    "visual"
+                }
+            }
+            _ => "",
+        };
+        // The status lines of the bottom panes cover what the prompt left.
+        let prompt_row = self.screen.size().1.saturating_sub(1);
+        self.screen.clear_line(0, prompt_row);
+        if self.mode == Mode::Quickfix {
+            self.editor.render_quickfix(&mut self.screen);
+        } else {
+            self.editor.render(&mut self.screen, state);
+        }
         if let Some(ref mut prompt) = self.prompt {
-            prompt.render(self.tty.stdout(), self.tty_size.1)?;
+            if prompt.search_direction().is_some() {
+                prompt.set_status(self.editor.search_status());
+            }
+            prompt.render(&mut self.screen, prompt_row);
         } else {
-            let state = match self.mode {
-                Mode::Vix => "vix",
-                Mode::Insert => "insert",
-                Mode::Visual(line_mode) => {
-                    if line_mode {
-                        "visual line"
-                    } else {
-                        "visual"
-                    }
+            match &self.mode {
+                Mode::Error(msg) | Mode::Conflict(_, msg) => {
+                    self.editor.render_error(&mut self.screen, msg)
                 }
-                _ => "",
-            };
-            self.editor.render(self.tty.stdout(), state);
-            if let Mode::Error(msg) = &self.mode {
-                self.editor.render_error(self.tty.stdout(), msg);
+                Mode::Recover(_, journal) => {
+                    let msg = format!(
+                        "Found a recovery journal for {}  (R)ecover, (D)elete it, (E)dit anyway",
+                        journal.describe()
+                    );
+                    self.editor.render_error(&mut self.screen, &msg);
+                }
+                _ => {}
             }
         }
-        if let Err(err) = self.tty.stdout().flush() {
-            error!("Failed to flush stdout: {}", err);
-        }
-        Ok(())
+        self.screen.flush(&mut self.backend)
     }
 }
 
-impl Future for Vix {
+impl<B: Backend> Future for Vix<B> {
     type Item = ();
     type Error = io::Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // Requests made while handling events are polled afterwards, so that
+        // every pending future registers this task to be woken up.
+        // Keys wait until the changes of an undo command are made, so that
+        // they are not mixed with the changes they make.
+        if !self.editor.is_replaying() {
+            self.process_terminal_events();
+        }
+        if !self.editor.process_core_events() {
+            // Keep the unsaved changes in the journals for `vix -r`.
+            error!("xi-core exited, shutting down");
+            self.editor.write_journals();
+            self.editor.cancel_saves();
+            self.shutdown = true;
+        }
+        self.editor.process_replays();
+        self.process_checktime();
+        self.editor.process_search();
         self.editor.process_open_requests();
+        self.editor.process_clipboard_request();
+        if !self.editor.process_save_requests() && self.shutdown {
+            info!("not exiting, a buffer could not be saved");
+            self.shutdown = false;
+        }
         self.editor.process_delayed_events();
-        self.process_terminal_events();
-        self.editor.process_core_events();
-        if let Err(err) = self.render() {
-            error!("Error rendering: {}", err);
+        if self.mode == Mode::Vix && self.prompt.is_none() {
+            if let Some((view_id, journal)) = self.editor.take_recovery() {
+                self.mode = Mode::Recover(view_id, journal);
+                self.dirty = true;
+            }
+        }
+        if let Some(msg) = self.editor.take_error() {
+            self.mode = Mode::Error(msg);
+            self.dirty = true;
         }
-        if self.shutdown {
+        // Timers wake the loop up without changing anything most of the time.
+        if self.editor.take_changed() || self.dirty {
+            if let Err(err) = self.render() {
+                error!("Error rendering: {}", err);
+            }
+            self.dirty = false;
+        }
+        // Wait for pending saves before exiting, e.g. after `:wq`.
+        if self.shutdown && !self.editor.has_pending_saves() {
             Ok(Async::Ready(()))
         } else {
             Ok(Async::NotReady)
@@ -354,21 +739,21 @@ impl Future for Vix {
     }
 }
 
+type ServerResult<T> = Box<dyn Future<Item = T, Error = ()> + Send>;
+
 pub struct VixService(UnboundedSender<CoreEvent>);
 
 impl VixService {
     fn send_core_event(&mut self, event: CoreEvent) -> ServerResult<()> {
         if let Err(err) = self.0.start_send(event) {
-            let e = format!("Error starting send core event {}", err);
-            error!("{}", e);
-            return Box::new(future::err(e.into()));
+            error!("Error starting send core event {}", err);
+            return Box::new(future::err(()));
         }
         match self.0.poll_complete() {
             Ok(_) => Box::new(future::ok(())),
             Err(err) => {
-                let e = format!("Error completing send core event {}", err);
-                error!("{}", e);
-                Box::new(future::err(e.into()))
+                error!("Error completing send core event {}", err);
+                Box::new(future::err(()))
             }
         }
     }
@@ -385,6 +770,14 @@ impl VixService {
         self.send_core_event(CoreEvent::SetStyle(style))
     }
 
+    fn find_status(&mut self, status: FindStatus) -> ServerResult<()> {
+        self.send_core_event(CoreEvent::FindStatus(status))
+    }
+
+    fn alert(&mut self, alert: Alert) -> ServerResult<()> {
+        self.send_core_event(CoreEvent::Alert(alert))
+    }
+
     fn available_plugins(&mut self, _plugins: AvailablePlugins) -> ServerResult<()> {
         warn!("AvailablePlugins not implemented: {:?}", _plugins);
         Box::new(future::ok(()))
@@ -417,6 +810,9 @@ impl VixService {
 }
 
 impl Frontend for VixService {
+    type NotificationResult = ServerResult<()>;
+    type MeasureWidthResult = ServerResult<Vec<Vec<f32>>>;
+
     fn handle_notification(&mut self, notification: XiNotification) -> ServerResult<()> {
         use XiNotification as xi;
         match notification {
@@ -429,18 +825,12 @@ impl Frontend for VixService {
             xi::PluginStoped(plugin) => self.plugin_stoped(plugin),
             xi::ConfigChanged(config) => self.config_changed(config),
             xi::ThemeChanged(theme) => self.theme_changed(theme),
-            xi::Alert(_) => {
-                warn!("XiNotification::Alert not implemented!!!");
-                Box::new(future::ok(()))
-            }
+            xi::Alert(alert) => self.alert(alert),
             xi::AvailableThemes(_) => {
                 warn!("XiNotification::AvailableThemes not implemented!!!");
                 Box::new(future::ok(()))
             }
-            xi::FindStatus(_) => {
-                warn!("XiNotification::FindStatus not implemented!!!");
-                Box::new(future::ok(()))
-            }
+            xi::FindStatus(status) => self.find_status(status),
             xi::ReplaceStatus(_) => {
                 warn!("XiNotification::ReplaceStatus not implemented!!!");
                 Box::new(future::ok(()))
@@ -469,8 +859,103 @@ impl VixServiceBuilder {
     }
 }
 
-impl FrontendBuilder<VixService> for VixServiceBuilder {
+impl FrontendBuilder for VixServiceBuilder {
+    type Frontend = VixService;
+
     fn build(self, _client: Client) -> VixService {
         VixService(self.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::HeadlessBackend;
+    use std::process;
+    use tokio::runtime::current_thread::Runtime;
+
+    /// Runs `test` on vix drawing on a headless terminal, with a core that
+    /// ignores every request.
+    fn with_vix<F: FnOnce(&mut Vix<HeadlessBackend>)>(width: u16, height: u16, test: F) {
+        let mut runtime = Runtime::new().unwrap();
+        runtime
+            .block_on(future::lazy(move || {
+                let mut core = process::Command::new("sh");
+                core.args(["-c", "cat >/dev/null"]);
+                let (builder, events) = VixServiceBuilder::new();
+                let (client, _) = xrl::spawn_command(core, builder).unwrap();
+                let mut vix =
+                    Vix::with_backend(client, events, HeadlessBackend::new(width, height));
+                assert_eq!(vix.poll().unwrap(), Async::NotReady);
+                test(&mut vix);
+                Ok::<(), ()>(())
+            }))
+            .unwrap();
+    }
+
+    fn press(vix: &mut Vix<HeadlessBackend>, keys: &[Key]) -> Async<()> {
+        for key in keys {
+            vix.backend.push_event(TtyEvent::Input(Event::Key(*key)));
+        }
+        vix.poll().unwrap()
+    }
+
+    #[test]
+    fn the_command_prompt_is_drawn_on_the_last_row() {
+        with_vix(12, 4, |vix| {
+            assert_eq!(vix.backend.row_text(3), " ".repeat(12));
+            press(vix, &[Key::Char(':'), Key::Char('s'), Key::Char('e')]);
+            assert_eq!(vix.mode, Mode::Command);
+            assert_eq!(vix.backend.row_text(3), ":se         ");
+            assert_eq!(vix.backend.cursor(), (3, 3));
+
+            press(vix, &[Key::Esc]);
+            assert_eq!(vix.mode, Mode::Vix);
+            assert!(vix.prompt.is_none());
+            assert_eq!(vix.backend.row_text(3), " ".repeat(12));
+        });
+    }
+
+    #[test]
+    fn nothing_is_drawn_until_something_changes() {
+        with_vix(12, 4, |vix| {
+            vix.backend.take_writes();
+            assert_eq!(vix.poll().unwrap(), Async::NotReady);
+            assert_eq!(vix.backend.take_writes(), vec![]);
+
+            press(vix, &[Key::Char('/')]);
+            assert_eq!(vix.mode, Mode::Search);
+            assert_eq!(vix.backend.take_writes(), vec![((0, 3), "/".to_owned())]);
+        });
+    }
+
+    #[test]
+    fn the_journal_timer_is_off_without_modified_buffers() {
+        with_vix(12, 4, |vix| {
+            assert!(vix.journal.is_none());
+            assert_eq!(vix.poll().unwrap(), Async::NotReady);
+            assert!(vix.journal.is_none());
+        });
+    }
+
+    #[test]
+    fn a_resize_redraws_the_prompt() {
+        with_vix(12, 4, |vix| {
+            press(vix, &[Key::Char(':'), Key::Char('q')]);
+            vix.backend.resize(8, 6);
+            assert_eq!(vix.poll().unwrap(), Async::NotReady);
+            assert_eq!(vix.backend.clears(), 2);
+            assert_eq!(vix.backend.row_text(3), " ".repeat(8));
+            assert_eq!(vix.backend.row_text(5), ":q      ");
+        });
+    }
+
+    #[test]
+    fn ctrl_c_exits_without_modified_buffers() {
+        with_vix(12, 4, |vix| {
+            assert_eq!(press(vix, &[Key::Char('i')]), Async::NotReady);
+            assert_eq!(vix.mode, Mode::Insert);
+            assert_eq!(press(vix, &[Key::Ctrl('c')]), Async::Ready(()));
+        });
+    }
+}
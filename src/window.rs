@@ -1,10 +1,17 @@
+use crate::layout::Rect;
 use crate::view::Cursor;
+use std::cmp;
 
 #[derive(Clone, Debug)]
 pub struct Window {
     cursor: Cursor,
     start: u64,
+    /// The first text column shown, when lines are not wrapped.
+    left: u16,
     size: u16,
+    x: u16,
+    y: u16,
+    width: u16,
 }
 
 impl Window {
@@ -12,67 +19,142 @@ impl Window {
         Window {
             cursor: Cursor { column: 0, line: 0 },
             start: 0,
+            left: 0,
             size: 0,
+            x: 0,
+            y: 0,
+            width: 0,
         }
     }
 
     pub fn set_cursor(&mut self, cursor: &Cursor) {
         self.cursor = cursor.clone();
         debug!("setting cursor to {:?}", cursor);
-        if cursor.line < self.start() {
-            self.start = cursor.line;
-        } else if cursor.line >= self.end() {
-            self.start = 1 + cursor.line - u64::from(self.size);
-        }
-        debug!("new window: {:?}", self);
     }
 
-    pub fn get_cursor(&mut self) -> Cursor {
+    pub fn get_cursor(&self) -> Cursor {
         self.cursor.clone()
     }
 
-    pub fn resize(&mut self, height: u16) {
-        self.size = height;
+    /// Places the window at `rect`, keeping the last row for the status line.
+    pub fn set_rect(&mut self, rect: Rect) {
+        self.x = rect.x;
+        self.y = rect.y;
+        self.width = rect.width;
+        self.size = rect.height.saturating_sub(1);
     }
 
     pub fn size(&self) -> u16 {
         self.size
     }
 
+    pub fn x(&self) -> u16 {
+        self.x
+    }
+
+    pub fn y(&self) -> u16 {
+        self.y
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
     pub fn start(&self) -> u64 {
         self.start
     }
 
+    pub fn left(&self) -> u16 {
+        self.left
+    }
+
+    pub fn set_left(&mut self, left: u16) {
+        self.left = left;
+    }
+
+    /// Scrolls horizontally so that `column` is shown in the `width` text
+    /// columns, `sidescrolloff` columns away from the edges. The text is
+    /// scrolled by at least `sidescroll` columns, or the column is centered
+    /// if it is 0.
+    pub fn follow_column(&mut self, column: u16, width: u16, sidescroll: u16, sidescrolloff: u16) {
+        if width == 0 {
+            return;
+        }
+        let offset = cmp::min(sidescrolloff, (width - 1) / 2);
+        let min_left = (column + offset + 1).saturating_sub(width);
+        let max_left = column.saturating_sub(offset);
+        if self.left >= min_left && self.left <= max_left {
+            return;
+        }
+        let left = if sidescroll == 0 {
+            column.saturating_sub(width / 2)
+        } else if self.left > max_left {
+            self.left.saturating_sub(sidescroll)
+        } else {
+            self.left + sidescroll
+        };
+        self.left = cmp::max(min_left, cmp::min(max_left, left));
+    }
+
     pub fn end(&self) -> u64 {
         u64::from(self.size) + self.start
     }
 
-    pub fn update(&mut self, cursor: u64, nb_line: u64) {
+    pub fn set_start(&mut self, start: u64) {
+        self.start = start;
+    }
+
+    /// Scrolls as little as possible to show the cursor line and
+    /// `scrolloff` lines around it, `height` giving the rows each line
+    /// takes.
+    pub fn update<F: Fn(u64) -> u64>(
+        &mut self,
+        cursor: u64,
+        nb_line: u64,
+        scrolloff: u16,
+        height: F,
+    ) {
         debug!(
-            "resizing window: height: {}; cursor: {}; nb_line: {}",
+            "updating window: height: {}; cursor: {}; nb_line: {}",
             self.size, cursor, nb_line
         );
-
-        let mut new_start = if u64::from(self.size) > self.start() + self.end() {
-            0
-        } else {
-            (self.start + self.end() - u64::from(self.size)) / 2
-        };
-
-        if new_start + u64::from(self.size) > nb_line {
-            if nb_line < u64::from(self.size) {
-                new_start = 0;
-            } else {
-                new_start = nb_line - u64::from(self.size);
-            }
+        let size = u64::from(self.size);
+        if size == 0 {
+            return;
         }
-        if cursor < new_start {
-            new_start = cursor;
-        } else if cursor > new_start + u64::from(self.size) {
-            new_start = cursor - u64::from(self.size);
+        let offset = cmp::min(u64::from(scrolloff), (size - 1) / 2);
+        let top = cursor.saturating_sub(offset);
+        if self.start > top {
+            self.start = top;
         }
+        let bottom = cmp::max(cursor, cmp::min(cursor + offset, nb_line.saturating_sub(1)));
+        let mut rows: u64 = (self.start..=bottom).map(&height).sum();
+        // Wrapped lines may not leave room for all of the offset, the cursor
+        // line is kept shown anyway.
+        while rows > size && self.start < cursor {
+            rows -= height(self.start);
+            self.start += 1;
+        }
+        debug!("updated window: {:?}", self);
+    }
 
-        self.start = new_start;
-        debug!("resized window: {:?}", self);
+    pub fn show_rows_above<F: Fn(u64) -> u64>(&mut self, cursor: u64, rows: u64, height: F) {
+        let mut start = cursor;
+        let mut above = 0;
+        while start > 0 && above + height(start - 1) <= rows {
+            start -= 1;
+            above += height(start);
+        }
+        self.start = start;
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Scroll {
+    CursorTop,
+    CursorCenter,
+    CursorBottom,
+    Lines(i64),
+    HalfPages(i64),
+    Pages(i64),
+}
@@ -0,0 +1,253 @@
+use std::cmp;
+use std::ops::Range;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthChar;
+
+/// How long lines are broken into screen rows with `wrap`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Wrap {
+    /// Break after blanks rather than at the last column.
+    pub linebreak: bool,
+    /// Shown at the start of continuation rows.
+    pub showbreak: String,
+}
+
+/// The columns between tab stops.
+pub const TAB_WIDTH: u16 = 4;
+
+/// Returns the control character a grapheme consists of, if any. These are
+/// rendered in caret notation, e.g. '^B'.
+pub fn control_char(grapheme: &str) -> Option<char> {
+    match grapheme.chars().next() {
+        Some(c @ '\x00'..='\x08') | Some(c @ '\x0a'..='\x1f') | Some(c @ '\x7f') => Some(c),
+        _ => None,
+    }
+}
+
+/// Returns a control character in caret notation, i.e. '\x02' is '^B'.
+pub fn caret_notation(c: char) -> String {
+    format!("^{}", (c as u8 ^ 0x40u8) as char)
+}
+
+/// Returns the number of columns `grapheme` takes at column `position`.
+pub fn grapheme_width(grapheme: &str, position: u16, tab_width: u16) -> u16 {
+    if grapheme == "\t" {
+        return tab_width - (position % tab_width);
+    }
+    if control_char(grapheme).is_some() {
+        return 2;
+    }
+    // Combining marks and joined characters take no columns of their own,
+    // but a variation selector makes an emoji wide.
+    let base = grapheme.chars().next().and_then(|c| c.width()).unwrap_or(0) as u16;
+    if grapheme.contains('\u{fe0f}') {
+        cmp::max(base, 2)
+    } else {
+        base
+    }
+}
+
+/// The screen rows a line is shown on. Lines are laid out by grapheme
+/// clusters, positions in the line are byte offsets as used by xi-core.
+#[derive(Debug)]
+pub struct LineLayout {
+    offsets: Vec<usize>,
+    widths: Vec<u16>,
+    len: usize,
+    /// The graphemes shown on each row.
+    rows: Vec<Range<usize>>,
+    /// The columns taken by `showbreak` on continuation rows.
+    showbreak: u16,
+}
+
+impl LineLayout {
+    /// Lays out `text` in rows of `width` columns, or on a single row if
+    /// `wrap` is `None`.
+    pub fn new(text: &str, width: u16, tab_width: u16, wrap: Option<&Wrap>) -> Self {
+        let graphemes: Vec<(usize, &str)> = text.grapheme_indices(true).collect();
+        let offsets = graphemes.iter().map(|(offset, _)| *offset).collect();
+        let mut position: u16 = 0;
+        let widths: Vec<u16> = graphemes
+            .iter()
+            .map(|(_, grapheme)| {
+                let width = grapheme_width(grapheme, position, tab_width);
+                position = position.saturating_add(width);
+                width
+            })
+            .collect();
+        let wrap = match wrap {
+            Some(wrap) if width > 0 => wrap,
+            _ => {
+                return LineLayout {
+                    offsets,
+                    rows: vec![Range {
+                        start: 0,
+                        end: widths.len(),
+                    }],
+                    widths,
+                    len: text.len(),
+                    showbreak: 0,
+                };
+            }
+        };
+        // The marker is left out if it would not leave room for the text.
+        let showbreak = match wrap.showbreak.chars().count() as u16 {
+            len if len < width => len,
+            _ => 0,
+        };
+
+        let mut rows = Vec::new();
+        let mut start = 0;
+        let mut used = 0;
+        let mut available = width;
+        let mut last_blank = None;
+        for (idx, (_, grapheme)) in graphemes.iter().enumerate() {
+            let char_width = widths[idx];
+            if used + char_width > available && idx > start {
+                available = width - showbreak;
+                let carried = |from: usize| widths[from..idx].iter().sum::<u16>();
+                let end = match last_blank {
+                    Some(blank) if wrap.linebreak && carried(blank) + char_width <= available => {
+                        blank
+                    }
+                    _ => idx,
+                };
+                rows.push(start..end);
+                start = end;
+                used = carried(end);
+                last_blank = None;
+            }
+            used += char_width;
+            if *grapheme == " " || *grapheme == "\t" {
+                last_blank = Some(idx + 1);
+            }
+        }
+        rows.push(start..graphemes.len());
+        LineLayout {
+            offsets,
+            widths,
+            len: text.len(),
+            rows,
+            showbreak,
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn row(&self, row: usize) -> Range<usize> {
+        self.rows.get(row).cloned().unwrap_or(0..0)
+    }
+
+    pub fn width(&self, idx: usize) -> u16 {
+        self.widths.get(idx).cloned().unwrap_or(1)
+    }
+
+    pub fn row_width(&self, row: usize) -> u16 {
+        self.row(row).map(|idx| self.width(idx)).sum()
+    }
+
+    pub fn indent(&self, row: usize) -> u16 {
+        if row == 0 {
+            0
+        } else {
+            self.showbreak
+        }
+    }
+
+    /// The byte offset of the grapheme at `idx`, the end of the line for an
+    /// index past it.
+    fn offset(&self, idx: usize) -> usize {
+        self.offsets.get(idx).cloned().unwrap_or(self.len)
+    }
+
+    /// Returns the row and column the grapheme at byte `offset` is shown at,
+    /// the end of the line for an offset past it.
+    pub fn position(&self, offset: usize) -> (usize, u16) {
+        let idx = if offset >= self.len {
+            self.widths.len()
+        } else {
+            self.offsets
+                .iter()
+                .rposition(|start| *start <= offset)
+                .unwrap_or(0)
+        };
+        let row = self
+            .rows
+            .iter()
+            .position(|range| idx < range.end)
+            .unwrap_or(self.rows.len() - 1);
+        let start = self.rows[row].start;
+        let end = cmp::min(idx, self.widths.len());
+        let column = self.indent(row) + self.widths[start..end].iter().sum::<u16>();
+        (row, column)
+    }
+
+    /// Returns the byte offset of the grapheme shown at `column` of `row`.
+    pub fn offset_at(&self, row: usize, column: u16) -> usize {
+        let row = cmp::min(row, self.rows.len() - 1);
+        let range = self.rows[row].clone();
+        let mut position = self.indent(row);
+        for idx in range.clone() {
+            position += self.widths[idx];
+            if position > column {
+                return self.offset(idx);
+            }
+        }
+        // Past the end of a continued row, stay on that row.
+        if row + 1 < self.rows.len() && range.end > range.start {
+            self.offset(range.end - 1)
+        } else {
+            self.offset(range.end)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graphemes_take_their_display_width() {
+        assert_eq!(grapheme_width("a", 0, 4), 1);
+        assert_eq!(grapheme_width("中", 0, 4), 2);
+        assert_eq!(grapheme_width("👍", 0, 4), 2);
+        assert_eq!(grapheme_width("❤\u{fe0f}", 0, 4), 2);
+        assert_eq!(grapheme_width("e\u{301}", 0, 4), 1);
+        assert_eq!(grapheme_width("\u{301}", 0, 4), 0);
+        assert_eq!(grapheme_width("\u{200b}", 0, 4), 0);
+        assert_eq!(grapheme_width("\x01", 0, 4), 2);
+        assert_eq!(grapheme_width("\t", 1, 4), 3);
+    }
+
+    #[test]
+    fn positions_and_offsets_round_trip() {
+        // Offsets 0, 1, 4, 7 and 11, columns 0, 1, 3, 4 and 6.
+        let text = "a中e\u{301}👍b";
+        let layout = LineLayout::new(text, 80, 4, None);
+        for (offset, column) in &[(0, 0), (1, 1), (4, 3), (7, 4), (11, 6), (12, 7)] {
+            assert_eq!(layout.position(*offset), (0, *column));
+            assert_eq!(layout.offset_at(0, *column), *offset);
+        }
+        // Inside a grapheme, or on the second column of a wide one.
+        assert_eq!(layout.position(5), (0, 3));
+        assert_eq!(layout.offset_at(0, 2), 1);
+        assert_eq!(layout.offset_at(0, 5), 7);
+    }
+
+    #[test]
+    fn wide_graphemes_wrap_as_a_whole() {
+        let layout = LineLayout::new("a中e\u{301}👍b", 4, 4, Some(&Wrap::default()));
+        assert_eq!(layout.height(), 2);
+        assert_eq!(layout.row_width(0), 4);
+        assert_eq!(layout.position(7), (1, 0));
+        assert_eq!(layout.position(12), (1, 3));
+        assert_eq!(layout.offset_at(1, 1), 7);
+        assert_eq!(layout.offset_at(0, 3), 4);
+
+        let layout = LineLayout::new("abc中", 4, 4, Some(&Wrap::default()));
+        assert_eq!(layout.row_width(0), 3);
+        assert_eq!(layout.position(3), (1, 0));
+    }
+}